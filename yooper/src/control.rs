@@ -0,0 +1,228 @@
+//! Control is the third step of UPnP, after [Description](../description).
+//! Using a service's control URL, invoke its actions with SOAP.
+mod codes;
+#[cfg(test)]
+mod tests;
+
+use std::fmt;
+
+use bytes::Bytes;
+use url::Url;
+use xml::common::XmlVersion;
+use xml::writer::{EmitterConfig, XmlEvent};
+
+use crate::description::{
+    read_document, Extension, HttpFetcher, Post, Service, ServiceDescription, Value,
+};
+use crate::ssdp::packet::Headers;
+use crate::Error;
+
+pub use codes::{ErrorCode, FirewallControlError, StandardError, WANConnectionError};
+
+/// Generate a typed client for a service from its SCPD document.
+///
+/// ```ignore
+/// yooper::control::client! {
+///     pub mod wan_ip_connection = "scpd/WANIPConnection1.xml";
+/// }
+///
+/// let client = wan_ip_connection::Client::new(service)?;
+/// let status = client.get_status_info().await?;
+/// if status.new_connection_status == wan_ip_connection::ConnectionStatus::Connected { ... }
+/// ```
+pub use yooper_derive::client;
+
+const ENVELOPE_NAMESPACE: &str = "http://schemas.xmlsoap.org/soap/envelope/";
+const ENCODING_STYLE: &str = "http://schemas.xmlsoap.org/soap/encoding/";
+const CONTROL_NAMESPACE: &str = "urn:schemas-upnp-org:control-1-0";
+
+/// The error a service returns when an action fails
+#[derive(Debug, PartialEq, Clone)]
+pub struct UPnPError {
+    /// 401-799 are defined by UPnP and service specifications, 800-899 by vendors
+    pub code: u16,
+    /// What `code` means, to match on
+    pub kind: ErrorCode,
+    pub description: String,
+}
+
+impl fmt::Display for UPnPError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UPnP error {}: {}", self.code, self.description)
+    }
+}
+
+/// Invoke `action` on `service`. The in-arguments are sent as given, in the order given,
+/// and services expect them in the order their description lists them.
+/// [invoke_checked](fn.invoke_checked.html) rejects arguments that are out of order.
+/// Returns the out-arguments, in the order the service sent them.
+/// The service should come from [describe](../description/fn.describe.html), so its URLs are absolute.
+pub async fn invoke(
+    service: &Service,
+    action: &str,
+    arguments: &[(&str, &str)],
+) -> Result<Vec<(String, String)>, Error> {
+    invoke_with(&HttpFetcher::default(), service, action, arguments).await
+}
+
+/// Like [invoke](fn.invoke.html), but with your own client
+pub async fn invoke_with<P: Post + ?Sized>(
+    client: &P,
+    service: &Service,
+    action: &str,
+    arguments: &[(&str, &str)],
+) -> Result<Vec<(String, String)>, Error> {
+    let service_type = service.service_type.to_string();
+
+    let mut headers = Headers::new();
+    headers.insert("Content-Type", "text/xml; charset=\"utf-8\"");
+    headers.insert("SOAPAction", format!("\"{}#{}\"", service_type, action));
+    let body = envelope(&service_type, action, arguments)?;
+
+    let url = Url::parse(&service.control_url)?;
+    let response = client.post(&url, &headers, body).await?;
+
+    let success = (200..300).contains(&response.status);
+    // Errors don't always come with a SOAP fault, or even XML
+    let envelope = match read_document(&response.body, false) {
+        Ok(envelope) => envelope,
+        Err(e) if success => return Err(e),
+        Err(_) => None,
+    };
+    let body = envelope.as_ref().and_then(|envelope| {
+        find(envelope, ENVELOPE_NAMESPACE, "Body").and_then(|body| body.children.first())
+    });
+    match body.cloned() {
+        Some(fault) if fault.is(ENVELOPE_NAMESPACE, "Fault") => Err(upnp_error(&fault, service)
+            .map(Error::UPnPError)
+            .unwrap_or(Error::HTTPStatus(response.status))),
+        _ if !success => Err(Error::HTTPStatus(response.status)),
+        Some(out) if out.name == format!("{}Response", action) => Ok(out
+            .children
+            .into_iter()
+            .map(|arg| (arg.name, arg.text))
+            .collect()),
+        _ => Err(Error::ParseFailure(format!(
+            "expected a {}Response in the SOAP body",
+            action
+        ))),
+    }
+}
+
+/// Like [invoke](fn.invoke.html), but with typed arguments that are checked against the
+/// service's description before anything is sent. Out-arguments are typed the same way.
+pub async fn invoke_checked(
+    service: &Service,
+    scpd: &ServiceDescription,
+    action: &str,
+    arguments: &[(&str, Value)],
+) -> Result<Vec<(String, Value)>, Error> {
+    invoke_checked_with(&HttpFetcher::default(), service, scpd, action, arguments).await
+}
+
+/// Like [invoke_checked](fn.invoke_checked.html), but with your own client
+pub async fn invoke_checked_with<P: Post + ?Sized>(
+    client: &P,
+    service: &Service,
+    scpd: &ServiceDescription,
+    action: &str,
+    arguments: &[(&str, Value)],
+) -> Result<Vec<(String, Value)>, Error> {
+    scpd.check_arguments(action, arguments)?;
+
+    let formatted: Vec<(&str, String)> = arguments
+        .iter()
+        .map(|(name, value)| (*name, value.to_string()))
+        .collect();
+    let formatted: Vec<(&str, &str)> = formatted
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .collect();
+    let out = invoke_with(client, service, action, &formatted).await?;
+
+    let definition = scpd
+        .action(action)
+        .ok_or_else(|| Error::UnknownAction(action.to_owned()))?;
+    out.into_iter()
+        .map(|(name, text)| {
+            let argument = definition
+                .out_arguments()
+                .find(|a| a.name == name)
+                .ok_or_else(|| {
+                    Error::ParseFailure(format!("{} has no out-argument {}", action, name))
+                })?;
+            let data_type = &scpd.related_state_variable(argument)?.data_type;
+            let value = Value::parse(data_type, &text)?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// Remove an out-argument from what [invoke_checked](fn.invoke_checked.html) returned,
+/// converting it to the type a generated client expects
+#[doc(hidden)]
+pub fn take_out_argument<T>(
+    out: &mut Vec<(String, Value)>,
+    name: &str,
+    convert: impl FnOnce(Value) -> Option<T>,
+) -> Result<T, Error> {
+    let i = out
+        .iter()
+        .position(|(n, _)| n == name)
+        .ok_or_else(|| Error::ParseFailure(format!("response is missing {}", name)))?;
+    let (_, value) = out.remove(i);
+    let text = value.to_string();
+    convert(value).ok_or_else(|| Error::MalformedField("out-argument", text))
+}
+
+/// The SOAP request for an action
+fn envelope(service_type: &str, action: &str, arguments: &[(&str, &str)]) -> Result<Bytes, Error> {
+    let mut buf = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut buf);
+
+    w.write(XmlEvent::StartDocument {
+        version: XmlVersion::Version10,
+        encoding: Some("utf-8"),
+        standalone: None,
+    })?;
+    w.write(
+        XmlEvent::start_element("s:Envelope")
+            .ns("s", ENVELOPE_NAMESPACE)
+            .attr("s:encodingStyle", ENCODING_STYLE),
+    )?;
+    w.write(XmlEvent::start_element("s:Body"))?;
+    w.write(XmlEvent::start_element(format!("u:{}", action).as_str()).ns("u", service_type))?;
+    for (name, value) in arguments {
+        w.write(XmlEvent::start_element(*name))?;
+        w.write(XmlEvent::characters(value))?;
+        w.write(XmlEvent::end_element())?;
+    }
+    w.write(XmlEvent::end_element())?;
+    w.write(XmlEvent::end_element())?;
+    w.write(XmlEvent::end_element())?;
+
+    Ok(buf.into())
+}
+
+fn find<'a>(element: &'a Extension, namespace: &str, name: &str) -> Option<&'a Extension> {
+    element.children.iter().find(|c| c.is(namespace, name))
+}
+
+/// The UPnPError in a SOAP fault's details, if that's what it is
+fn upnp_error(fault: &Extension, service: &Service) -> Option<UPnPError> {
+    // Unqualified, unlike the rest of the fault
+    let detail = fault.children.iter().find(|c| c.name == "detail")?;
+    let error = find(detail, CONTROL_NAMESPACE, "UPnPError")?;
+
+    let code = find(error, CONTROL_NAMESPACE, "errorCode")?
+        .text
+        .trim()
+        .parse()
+        .ok()?;
+    Some(UPnPError {
+        code,
+        kind: ErrorCode::new(code, &service.service_type),
+        description: find(error, CONTROL_NAMESPACE, "errorDescription")
+            .map_or(String::new(), |d| d.text.trim().to_owned()),
+    })
+}
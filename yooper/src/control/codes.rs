@@ -0,0 +1,143 @@
+//! What the codes in a [UPnPError](../struct.UPnPError.html) mean
+use crate::description::ServiceType;
+
+/// Declare an enum of error codes, converting to and from the number
+macro_rules! error_codes {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $code:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+        }
+
+        impl $name {
+            pub fn from_code(code: u16) -> Option<Self> {
+                match code {
+                    $($code => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+
+            pub fn code(self) -> u16 {
+                match self {
+                    $(Self::$variant => $code,)*
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// Codes the UPnP Device Architecture defines for every service.
+    /// It leaves 613-699 unassigned, so those are [Unknown](enum.ErrorCode.html#variant.Unknown).
+    pub enum StandardError {
+        InvalidAction = 401,
+        /// Missing, extra or misordered arguments
+        InvalidArgs = 402,
+        /// The action failed for a reason other than its arguments
+        ActionFailed = 501,
+        ArgumentValueInvalid = 600,
+        ArgumentValueOutOfRange = 601,
+        OptionalActionNotImplemented = 602,
+        OutOfMemory = 603,
+        HumanInterventionRequired = 604,
+        StringArgumentTooLong = 605,
+        ActionNotAuthorized = 606,
+        SignatureFailure = 607,
+        SignatureMissing = 608,
+        NotEncrypted = 609,
+        InvalidSequence = 610,
+        InvalidControlURL = 611,
+        NoSuchSession = 612,
+    }
+}
+
+error_codes! {
+    /// Codes WANIPConnection and WANPPPConnection define, mostly about port mappings
+    pub enum WANConnectionError {
+        InactiveConnectionStateRequired = 703,
+        ConnectionSetupFailed = 704,
+        ConnectionSetupInProgress = 705,
+        ConnectionNotConfigured = 706,
+        DisconnectInProgress = 707,
+        InvalidLayer2Address = 708,
+        InternetAccessDisabled = 709,
+        InvalidConnectionType = 710,
+        ConnectionAlreadyTerminated = 711,
+        SpecifiedArrayIndexInvalid = 713,
+        NoSuchEntryInArray = 714,
+        WildCardNotPermittedInSrcIP = 715,
+        WildCardNotPermittedInExtPort = 716,
+        /// The port is already mapped to another client
+        ConflictInMappingEntry = 718,
+        SamePortValuesRequired = 724,
+        OnlyPermanentLeasesSupported = 725,
+        RemoteHostOnlySupportsWildcard = 726,
+        ExternalPortOnlySupportsWildcard = 727,
+        NoPortMapsAvailable = 728,
+        ConflictWithOtherMechanisms = 729,
+        WildCardNotPermittedInIntPort = 732,
+    }
+}
+
+error_codes! {
+    /// Codes WANIPv6FirewallControl defines, about pinholes
+    pub enum FirewallControlError {
+        PinholeSpaceExhausted = 701,
+        FirewallDisabled = 702,
+        InboundPinholeNotAllowed = 703,
+        NoSuchEntry = 704,
+        ProtocolNotSupported = 705,
+        InternalPortWildcardingNotAllowed = 706,
+        ProtocolWildcardingNotAllowed = 707,
+        WildCardNotPermittedInSrcIP = 708,
+        NoPacketSent = 709,
+    }
+}
+
+/// What a UPnPError's code means, which for 700 and up depends on the service
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ErrorCode {
+    Standard(StandardError),
+    WANConnection(WANConnectionError),
+    FirewallControl(FirewallControlError),
+    /// Defined by a service we don't know, or a vendor, or nobody
+    Unknown(u16),
+}
+
+impl ErrorCode {
+    /// Interpret `code` as returned by a service of type `service_type`
+    pub fn new(code: u16, service_type: &ServiceType) -> Self {
+        if let Some(standard) = StandardError::from_code(code) {
+            return Self::Standard(standard);
+        }
+
+        let specific = match (
+            service_type.vendor_domain.as_deref(),
+            service_type.service_type.as_str(),
+        ) {
+            (None, "WANIPConnection") | (None, "WANPPPConnection") => {
+                WANConnectionError::from_code(code).map(Self::WANConnection)
+            }
+            (None, "WANIPv6FirewallControl") => {
+                FirewallControlError::from_code(code).map(Self::FirewallControl)
+            }
+            _ => None,
+        };
+        specific.unwrap_or(Self::Unknown(code))
+    }
+
+    pub fn code(self) -> u16 {
+        match self {
+            Self::Standard(e) => e.code(),
+            Self::WANConnection(e) => e.code(),
+            Self::FirewallControl(e) => e.code(),
+            Self::Unknown(code) => code,
+        }
+    }
+}
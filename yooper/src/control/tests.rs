@@ -0,0 +1,406 @@
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use futures::future::{BoxFuture, FutureExt};
+use url::Url;
+
+use super::*;
+use crate::description::{Description, Response, ServiceDescription, ServiceType};
+
+const IGD_EXAMPLE: &str = include_str!("../description/testdata/igd.xml");
+
+/// Answers every post with the same response, remembering what it was sent
+struct FakeClient {
+    status: u16,
+    body: &'static str,
+    requests: Mutex<Vec<(Url, Headers, Bytes)>>,
+}
+
+impl FakeClient {
+    fn new(status: u16, body: &'static str) -> Self {
+        Self {
+            status,
+            body,
+            requests: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl Post for FakeClient {
+    fn post<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+        body: Bytes,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((url.clone(), headers.clone(), body));
+        let response = Response {
+            url: url.clone(),
+            status: self.status,
+            headers: Headers::new(),
+            body: Bytes::from_static(self.body.as_bytes()),
+        };
+        async move { Ok(response) }.boxed()
+    }
+}
+
+fn wan_ip_connection() -> Service {
+    let mut description = Description::from_slice(IGD_EXAMPLE.as_bytes()).unwrap();
+    description
+        .resolve_urls(&Url::parse("http://192.168.7.1:1900/igd.xml").unwrap())
+        .unwrap();
+    description
+        .device
+        .devices
+        .remove(0)
+        .devices
+        .remove(0)
+        .services
+        .remove(0)
+}
+
+const RESPONSE: &str = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+  <s:Body>
+    <u:GetGenericPortMappingEntryResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+      <NewRemoteHost></NewRemoteHost>
+      <NewExternalPort>8080</NewExternalPort>
+      <NewProtocol>TCP</NewProtocol>
+      <NewInternalClient>192.168.7.42</NewInternalClient>
+      <NewPortMappingDescription> my server </NewPortMappingDescription>
+    </u:GetGenericPortMappingEntryResponse>
+  </s:Body>
+</s:Envelope>"#;
+
+const FAULT: &str = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+  <s:Body>
+    <s:Fault>
+      <faultcode>s:Client</faultcode>
+      <faultstring>UPnPError</faultstring>
+      <detail>
+        <UPnPError xmlns="urn:schemas-upnp-org:control-1-0">
+          <errorCode>713</errorCode>
+          <errorDescription>SpecifiedArrayIndexInvalid</errorDescription>
+        </UPnPError>
+      </detail>
+    </s:Fault>
+  </s:Body>
+</s:Envelope>"#;
+
+#[tokio::test]
+async fn test_invoke() {
+    let client = FakeClient::new(200, RESPONSE);
+    let out = invoke_with(
+        &client,
+        &wan_ip_connection(),
+        "GetGenericPortMappingEntry",
+        &[("NewPortMappingIndex", "0")],
+    )
+    .await
+    .unwrap();
+
+    let out: Vec<(&str, &str)> = out.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+    assert_eq!(
+        out,
+        vec![
+            ("NewRemoteHost", ""),
+            ("NewExternalPort", "8080"),
+            ("NewProtocol", "TCP"),
+            ("NewInternalClient", "192.168.7.42"),
+            ("NewPortMappingDescription", " my server "),
+        ]
+    );
+
+    let requests = client.requests.lock().unwrap();
+    let (url, headers, body) = &requests[0];
+    assert_eq!(url.as_str(), "http://192.168.7.1:1900/ipc");
+    assert_eq!(
+        headers.get("soapaction").unwrap(),
+        "\"urn:schemas-upnp-org:service:WANIPConnection:1#GetGenericPortMappingEntry\""
+    );
+    assert_eq!(
+        headers.get("content-type").unwrap(),
+        "text/xml; charset=\"utf-8\""
+    );
+
+    let envelope = read_document(body, false).unwrap().unwrap();
+    assert!(envelope.is(ENVELOPE_NAMESPACE, "Envelope"));
+    assert_eq!(envelope.attribute("encodingStyle"), Some(ENCODING_STYLE));
+    let action = &find(&envelope, ENVELOPE_NAMESPACE, "Body")
+        .unwrap()
+        .children[0];
+    assert!(action.is(
+        "urn:schemas-upnp-org:service:WANIPConnection:1",
+        "GetGenericPortMappingEntry"
+    ));
+    assert_eq!(action.children[0].name, "NewPortMappingIndex");
+    assert_eq!(action.children[0].text, "0");
+}
+
+#[tokio::test]
+async fn test_fault() {
+    let client = FakeClient::new(500, FAULT);
+    let result = invoke_with(
+        &client,
+        &wan_ip_connection(),
+        "GetGenericPortMappingEntry",
+        &[("NewPortMappingIndex", "12")],
+    )
+    .await;
+
+    match result {
+        Err(Error::UPnPError(e)) => assert_eq!(
+            e,
+            UPnPError {
+                code: 713,
+                kind: ErrorCode::WANConnection(WANConnectionError::SpecifiedArrayIndexInvalid),
+                description: "SpecifiedArrayIndexInvalid".into()
+            }
+        ),
+        other => panic!("expected a UPnPError, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_unexpected_responses() {
+    let service = wan_ip_connection();
+
+    let client = FakeClient::new(404, "");
+    match invoke_with(&client, &service, "GetExternalIPAddress", &[]).await {
+        Err(Error::HTTPStatus(404)) => (),
+        other => panic!("expected a 404, got {:?}", other),
+    }
+
+    let client = FakeClient::new(200, RESPONSE);
+    match invoke_with(&client, &service, "GetExternalIPAddress", &[]).await {
+        Err(Error::ParseFailure(_)) => (),
+        other => panic!("expected a parse failure, got {:?}", other),
+    }
+}
+
+const WAN_IP_CONNECTION_SCPD: &str = include_str!("../description/testdata/wanipconnection.xml");
+
+const STATUS_RESPONSE: &str = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+  <s:Body>
+    <u:GetStatusInfoResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+      <NewConnectionStatus>Connected</NewConnectionStatus>
+      <NewLastConnectionError>ERROR_NONE</NewLastConnectionError>
+      <NewUptime>86400</NewUptime>
+    </u:GetStatusInfoResponse>
+  </s:Body>
+</s:Envelope>"#;
+
+#[tokio::test]
+async fn test_invoke_checked() {
+    let scpd: ServiceDescription = WAN_IP_CONNECTION_SCPD.parse().unwrap();
+    let service = wan_ip_connection();
+
+    let client = FakeClient::new(200, STATUS_RESPONSE);
+    let out = invoke_checked_with(&client, &service, &scpd, "GetStatusInfo", &[])
+        .await
+        .unwrap();
+    assert_eq!(
+        out,
+        vec![
+            (
+                "NewConnectionStatus".to_owned(),
+                Value::String("Connected".into())
+            ),
+            (
+                "NewLastConnectionError".to_owned(),
+                Value::String("ERROR_NONE".into())
+            ),
+            ("NewUptime".to_owned(), Value::UI4(86400)),
+        ]
+    );
+
+    // Nothing is sent when the arguments are wrong
+    let client = FakeClient::new(200, RESPONSE);
+    let result = invoke_checked_with(
+        &client,
+        &service,
+        &scpd,
+        "DeletePortMapping",
+        &[
+            ("NewRemoteHost", Value::String("".into())),
+            ("NewExternalPort", Value::UI2(8080)),
+            ("NewProtocol", Value::String("ICMP".into())),
+        ],
+    )
+    .await;
+    assert!(matches!(result, Err(Error::DisallowedValue(..))));
+    assert!(client.requests.lock().unwrap().is_empty());
+}
+
+client! {
+    /// WANIPConnection:1, as described by the test router
+    mod wan_ip_connection = "src/description/testdata/wanipconnection.xml";
+}
+
+const EXTERNAL_IP_RESPONSE: &str = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+  <s:Body>
+    <u:GetExternalIPAddressResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+      <NewExternalIPAddress>203.0.113.7</NewExternalIPAddress>
+    </u:GetExternalIPAddressResponse>
+  </s:Body>
+</s:Envelope>"#;
+
+const DELETE_RESPONSE: &str = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+  <s:Body>
+    <u:DeletePortMappingResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1"/>
+  </s:Body>
+</s:Envelope>"#;
+
+#[tokio::test]
+async fn test_generated_client() {
+    use wan_ip_connection::{Client, ConnectionStatus, GetStatusInfoResponse, LastConnectionError};
+
+    let client =
+        Client::with_client(wan_ip_connection(), FakeClient::new(200, STATUS_RESPONSE)).unwrap();
+    assert_eq!(
+        client.get_status_info().await.unwrap(),
+        GetStatusInfoResponse {
+            new_connection_status: ConnectionStatus::Connected,
+            new_last_connection_error: LastConnectionError::ErrorNone,
+            new_uptime: 86400,
+        }
+    );
+
+    let client = Client::with_client(
+        wan_ip_connection(),
+        FakeClient::new(200, EXTERNAL_IP_RESPONSE),
+    )
+    .unwrap();
+    assert_eq!(
+        client.get_external_ip_address().await.unwrap(),
+        "203.0.113.7"
+    );
+
+    let client =
+        Client::with_client(wan_ip_connection(), FakeClient::new(200, DELETE_RESPONSE)).unwrap();
+    client
+        .delete_port_mapping(
+            String::new(),
+            8080,
+            wan_ip_connection::PortMappingProtocol::Udp,
+        )
+        .await
+        .unwrap();
+    let requests = client.client().requests.lock().unwrap();
+    let action = read_document(&requests[0].2, false).unwrap().unwrap();
+    let arguments: Vec<_> = action.children[0].children[0]
+        .children
+        .iter()
+        .map(|a| (a.name.as_str(), a.text.as_str()))
+        .collect();
+    assert_eq!(
+        arguments,
+        vec![
+            ("NewRemoteHost", ""),
+            ("NewExternalPort", "8080"),
+            ("NewProtocol", "UDP"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_generated_client_other_values() {
+    use wan_ip_connection::{Client, ConnectionStatus, PortMappingProtocol};
+
+    // Values the SCPD doesn't list are kept, and sent back as they came
+    let response = STATUS_RESPONSE.replace(">Connected<", ">Authenticating<");
+    let client = Client::with_client(
+        wan_ip_connection(),
+        FakeClient::new(200, Box::leak(response.into())),
+    )
+    .unwrap();
+    let status = client.get_status_info().await.unwrap();
+    assert_eq!(
+        status.new_connection_status,
+        ConnectionStatus::Other("Authenticating".into())
+    );
+    assert_eq!(status.new_connection_status.to_string(), "Authenticating");
+
+    let result = client
+        .delete_port_mapping(
+            String::new(),
+            8080,
+            PortMappingProtocol::Other("SCTP".into()),
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::DisallowedValue(name, value)) if name == "PortMappingProtocol" && value == "SCTP"
+    ));
+}
+
+#[tokio::test]
+async fn test_generated_client_checks_ranges() {
+    let client =
+        wan_ip_connection::Client::with_client(wan_ip_connection(), FakeClient::new(200, RESPONSE))
+            .unwrap();
+    let result = client
+        .add_port_mapping(
+            String::new(),
+            8080,
+            wan_ip_connection::PortMappingProtocol::Tcp,
+            0,
+            "192.168.7.42".into(),
+            true,
+            "my server".into(),
+            3600,
+        )
+        .await;
+    assert!(matches!(result, Err(Error::DisallowedValue(name, _)) if name == "InternalPort"));
+    assert!(client.client().requests.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_error_codes() {
+    let ip: ServiceType = "urn:schemas-upnp-org:service:WANIPConnection:2"
+        .parse()
+        .unwrap();
+    let ppp: ServiceType = "urn:schemas-upnp-org:service:WANPPPConnection:1"
+        .parse()
+        .unwrap();
+    let firewall: ServiceType = "urn:schemas-upnp-org:service:WANIPv6FirewallControl:1"
+        .parse()
+        .unwrap();
+    let vendor: ServiceType = "urn:example-com:service:WANIPConnection:1".parse().unwrap();
+
+    assert_eq!(
+        ErrorCode::new(402, &vendor),
+        ErrorCode::Standard(StandardError::InvalidArgs)
+    );
+    assert_eq!(
+        ErrorCode::new(718, &ip),
+        ErrorCode::WANConnection(WANConnectionError::ConflictInMappingEntry)
+    );
+    assert_eq!(
+        ErrorCode::new(714, &ppp),
+        ErrorCode::WANConnection(WANConnectionError::NoSuchEntryInArray)
+    );
+    assert_eq!(
+        ErrorCode::new(704, &firewall),
+        ErrorCode::FirewallControl(FirewallControlError::NoSuchEntry)
+    );
+    assert_eq!(ErrorCode::new(718, &vendor), ErrorCode::Unknown(718));
+    assert_eq!(ErrorCode::new(899, &ip), ErrorCode::Unknown(899));
+    assert_eq!(
+        ErrorCode::new(612, &ip),
+        ErrorCode::Standard(StandardError::NoSuchSession)
+    );
+    assert_eq!(ErrorCode::new(613, &ip), ErrorCode::Unknown(613));
+    assert_eq!(ErrorCode::new(614, &vendor), ErrorCode::Unknown(614));
+
+    for code in &[401, 606, 612, 613, 614, 714, 729, 899] {
+        assert_eq!(ErrorCode::new(*code, &ip).code(), *code);
+    }
+}
@@ -1,13 +1,38 @@
 //! Description is the second step of UPnP, after [Discovery](../Discovery).
 //! Using the location retrieved from discovery, retrieve an XML document over HTTP.
 //! This document enumerates the capabilities of the given device.
+mod cache;
+mod extensions;
+mod fetch;
+mod scpd;
+mod serialize;
 #[cfg(test)]
 mod tests;
-
+mod value;
+
+pub use cache::Cache;
+pub(crate) use extensions::read_document;
+pub use extensions::Extension;
+pub use fetch::{Fetch, HttpFetcher, Limits, Post, Response};
+pub use scpd::{
+    Action, AllowedValueRange, Argument, DataType, Direction, ServiceDescription, StateVariable,
+};
+pub use uuid::Uuid;
+pub use value::{Date, Time, TimeZone, Value};
+
+use crate::discovery;
+use crate::ssdp::packet::Headers;
 use crate::Error;
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Deserializer};
 use serde_with::rust::display_fromstr;
+use std::fmt;
+use std::path::Path;
 use std::str::FromStr;
+use url::Url;
+
+const DEVICE_NAMESPACE: &str = "urn:schemas-upnp-org:device-1-0";
 
 #[derive(PartialEq, Debug)]
 pub struct DeviceType {
@@ -17,9 +42,10 @@ pub struct DeviceType {
     pub version: String,
 }
 
-impl ToString for DeviceType {
-    fn to_string(&self) -> String {
-        format!(
+impl fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
             "urn:{}:device:{}:{}",
             self.vendor_domain
                 .as_ref()
@@ -30,6 +56,16 @@ impl ToString for DeviceType {
     }
 }
 
+impl DeviceType {
+    /// Whether a device of this type can stand in for one of type `wanted`.
+    /// Later versions of a type are backwards compatible with earlier ones.
+    pub fn is_compatible_with(&self, wanted: &Self) -> bool {
+        self.vendor_domain == wanted.vendor_domain
+            && self.device_type == wanted.device_type
+            && version_satisfies(&self.version, &wanted.version)
+    }
+}
+
 impl FromStr for DeviceType {
     type Err = Error;
 
@@ -51,7 +87,7 @@ impl FromStr for DeviceType {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ServiceType {
     /// Will be None for standard services specified by the UPnP Forum.
     pub vendor_domain: Option<String>,
@@ -59,6 +95,38 @@ pub struct ServiceType {
     pub version: String,
 }
 
+impl ServiceType {
+    /// Whether a service of this type can stand in for one of type `wanted`.
+    /// Later versions of a type are backwards compatible with earlier ones.
+    pub fn is_compatible_with(&self, wanted: &Self) -> bool {
+        self.vendor_domain == wanted.vendor_domain
+            && self.service_type == wanted.service_type
+            && version_satisfies(&self.version, &wanted.version)
+    }
+}
+
+/// Versions are integers, but compare vendors' odd ones exactly
+fn version_satisfies(version: &str, wanted: &str) -> bool {
+    match (version.parse::<u32>(), wanted.parse::<u32>()) {
+        (Ok(version), Ok(wanted)) => version >= wanted,
+        _ => version == wanted,
+    }
+}
+
+impl fmt::Display for ServiceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "urn:{}:service:{}:{}",
+            self.vendor_domain
+                .as_ref()
+                .map_or("schemas-upnp-org", String::as_ref),
+            self.service_type,
+            self.version,
+        )
+    }
+}
+
 impl FromStr for ServiceType {
     type Err = Error;
 
@@ -80,13 +148,26 @@ impl FromStr for ServiceType {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ServiceId {
     /// Will be None for standard services specified by the UPnP Forum
     pub vendor_domain: Option<String>,
     pub service_id: String,
 }
 
+impl fmt::Display for ServiceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "urn:{}:serviceId:{}",
+            self.vendor_domain
+                .as_ref()
+                .map_or("upnp-org", String::as_ref),
+            self.service_id,
+        )
+    }
+}
+
 impl FromStr for ServiceId {
     type Err = Error;
 
@@ -111,16 +192,21 @@ pub struct UniqueDeviceName {
     pub uuid: String,
 }
 
+impl fmt::Display for UniqueDeviceName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "uuid:{}", self.uuid)
+    }
+}
+
 impl FromStr for UniqueDeviceName {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.starts_with("uuid:") {
-            Ok(Self {
-                uuid: s[5..].to_owned(),
-            })
-        } else {
-            Err(Error::MalformedField("udn", s.to_owned()))
+        match s.strip_prefix("uuid:") {
+            Some(uuid) => Ok(Self {
+                uuid: uuid.to_owned(),
+            }),
+            None => Err(Error::MalformedField("udn", s.to_owned())),
         }
     }
 }
@@ -162,7 +248,9 @@ pub struct Device {
     /// Universal product code.
     pub upc: Option<String>,
 
-    // TODO(EKF): IconList
+    /// Icons to show the end user
+    #[serde(rename = "iconList", deserialize_with = "deserialize_icons", default)]
+    pub icons: Vec<Icon>,
     #[serde(
         rename = "serviceList",
         deserialize_with = "deserialize_services",
@@ -177,13 +265,149 @@ pub struct Device {
     pub devices: Vec<Device>,
 
     #[serde(rename = "presentationURL")]
-    /// A page to display to the end user
+    /// A page to display to the end user.
+    /// Absolute when returned from [describe](fn.describe.html), and None if empty
     pub presentation_url: Option<String>,
+
+    /// Vendor elements, like `<dlna:X_DLNADOC>`
+    #[serde(skip)]
+    pub extensions: Vec<Extension>,
+}
+
+impl Device {
+    /// Pick the icon that best fits a square of `size` pixels.
+    /// Prefers the smallest icon at least that big, then the biggest icon smaller than it.
+    /// If `mimetype` is given, only icons of that type are considered.
+    pub fn icon_for(&self, size: u32, mimetype: Option<&str>) -> Option<&Icon> {
+        let candidates = self
+            .icons
+            .iter()
+            .filter(|i| mimetype.is_none_or(|m| i.mimetype.eq_ignore_ascii_case(m)));
+
+        let (big_enough, too_small): (Vec<&Icon>, Vec<&Icon>) =
+            candidates.partition(|i| i.width >= size && i.height >= size);
+
+        big_enough
+            .into_iter()
+            .min_by_key(|i| (i.area(), std::cmp::Reverse(i.depth)))
+            .or_else(|| too_small.into_iter().max_by_key(|i| (i.area(), i.depth)))
+    }
+
+    /// Every device in the tree depth-first in document order, starting with this one.
+    /// Each comes with its path: the devices it's embedded in, outermost first,
+    /// not including the device itself. So this one's path is empty.
+    pub fn walk_devices(&self) -> Devices<'_> {
+        Devices {
+            stack: vec![(vec![], self)],
+        }
+    }
+
+    /// Every service in the tree, in the same order as [walk_devices](#method.walk_devices).
+    /// Each comes with its path: the devices it's in, outermost first,
+    /// so the last is the device that provides it.
+    pub fn walk_services(&self) -> impl Iterator<Item = (Vec<&Device>, &Service)> {
+        self.walk_devices().flat_map(|(mut path, device)| {
+            path.push(device);
+            device.services.iter().map(move |s| (path.clone(), s))
+        })
+    }
+
+    /// Find a service anywhere in the tree that's compatible with `service_type`
+    pub fn find_service(&self, service_type: &ServiceType) -> Option<&Service> {
+        self.walk_services()
+            .map(|(_, s)| s)
+            .find(|s| s.service_type.is_compatible_with(service_type))
+    }
+
+    /// Find a service anywhere in the tree by its ID
+    pub fn find_service_by_id(&self, service_id: &ServiceId) -> Option<&Service> {
+        self.walk_services()
+            .map(|(_, s)| s)
+            .find(|s| s.service_id == *service_id)
+    }
+
+    /// Find a device in the tree, including this one, that's compatible with `device_type`
+    pub fn find_device(&self, device_type: &DeviceType) -> Option<&Device> {
+        self.walk_devices()
+            .map(|(_, d)| d)
+            .find(|d| d.device_type.is_compatible_with(device_type))
+    }
+
+    /// Find a device in the tree, including this one, by its UDN
+    pub fn find_device_by_udn(&self, udn: &UniqueDeviceName) -> Option<&Device> {
+        self.walk_devices()
+            .map(|(_, d)| d)
+            .find(|d| d.unique_device_name == *udn)
+    }
+
+    /// Make the URLs of this device, its icons, services and embedded devices absolute
+    fn resolve_urls(&mut self, base: &Url) -> Result<(), Error> {
+        for icon in &mut self.icons {
+            resolve_url(base, "url", &mut icon.url)?;
+        }
+        for service in &mut self.services {
+            resolve_url(base, "SCPDURL", &mut service.scpd_url)?;
+            resolve_url(base, "controlURL", &mut service.control_url)?;
+            resolve_url(base, "eventSubURL", &mut service.event_sub_url)?;
+        }
+        if let Some(url) = &mut self.presentation_url {
+            resolve_url(base, "presentationURL", url)?;
+            if url.is_empty() {
+                self.presentation_url = None;
+            }
+        }
+        for device in &mut self.devices {
+            device.resolve_urls(base)?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over a device tree, see [Device::walk_devices](struct.Device.html#method.walk_devices)
+pub struct Devices<'a> {
+    stack: Vec<(Vec<&'a Device>, &'a Device)>,
+}
+
+impl<'a> Iterator for Devices<'a> {
+    type Item = (Vec<&'a Device>, &'a Device);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, device) = self.stack.pop()?;
+        for child in device.devices.iter().rev() {
+            let mut child_path = path.clone();
+            child_path.push(device);
+            self.stack.push((child_path, child));
+        }
+        Some((path, device))
+    }
+}
+
+/// An image representing a device
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct Icon {
+    /// Such as image/png
+    pub mimetype: String,
+    /// Horizontal dimension in pixels
+    pub width: u32,
+    /// Vertical dimension in pixels
+    pub height: u32,
+    /// Number of color bits per pixel
+    pub depth: u32,
+    /// Where to retrieve the image from.
+    /// Absolute when returned from [describe](fn.describe.html)
+    pub url: String,
+}
+
+impl Icon {
+    /// In u64, since the sizes come from the device and can be anything
+    fn area(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
 }
 
 /// Logical functional unit, Smallest  units of control.
 /// Exposes actions and models the state of a physical device with state variables.
-#[derive(Debug, PartialEq, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Service {
     #[serde(with = "display_fromstr")]
@@ -191,16 +415,23 @@ pub struct Service {
     #[serde(with = "display_fromstr")]
     pub service_id: ServiceId,
 
-    /// URL for service description. Relative to device description URL
+    /// URL for service description.
+    /// In the document, relative to the URLBase or device description URL.
+    /// Absolute when returned from [describe](fn.describe.html)
     #[serde(rename = "SCPDURL")]
     pub scpd_url: String,
 
-    /// Relative to device description URL
+    /// URL for control, resolved like `scpd_url`
     #[serde(rename = "controlURL")]
     pub control_url: String,
-    /// Relative to device description URL
+    /// URL for eventing, resolved like `scpd_url`.
+    /// Empty if the service has no evented variables
     #[serde(rename = "eventSubURL")]
     pub event_sub_url: String,
+
+    /// Vendor elements the service was described with
+    #[serde(skip)]
+    pub extensions: Vec<Extension>,
 }
 
 /// This document contains the root device description and metadata
@@ -209,9 +440,57 @@ pub struct Service {
 pub struct Description {
     pub config_id: Option<String>,
     pub spec_version: SpecVersion,
+    /// Base for relative URLs in UPnP 1.0 documents. Deprecated since UPnP 1.1
+    #[serde(rename = "URLBase")]
+    pub url_base: Option<String>,
     pub device: Device,
 }
 
+impl Description {
+    /// Parse a document you already have, such as one saved to disk.
+    /// URLs are left as they are in the document; see [resolve_urls](#method.resolve_urls).
+    pub fn from_slice(body: &[u8]) -> Result<Self, Error> {
+        let mut description: Self = serde_xml_rs::from_reader(body)?;
+        extensions::capture(body, &mut description.device)?;
+        Ok(description)
+    }
+
+    /// Make every URL in the document absolute, as per RFC 3986.
+    /// Relative URLs are resolved against the URLBase if there is one,
+    /// and otherwise against `location`, the URL the document was retrieved from.
+    pub fn resolve_urls(&mut self, location: &Url) -> Result<(), Error> {
+        let base = match &self.url_base {
+            Some(base) if !base.trim().is_empty() => location
+                .join(base.trim())
+                .map_err(|_| Error::MalformedField("URLBase", base.clone()))?,
+            _ => location.clone(),
+        };
+        self.device.resolve_urls(&base)
+    }
+}
+
+impl FromStr for Description {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_slice(s.as_bytes())
+    }
+}
+
+/// Resolve `url` against `base` in place, leaving empty URLs empty
+fn resolve_url(base: &Url, field: &'static str, url: &mut String) -> Result<(), Error> {
+    let trimmed = url.trim();
+    if trimmed.is_empty() {
+        url.clear();
+        return Ok(());
+    }
+    *url = base
+        .join(trimmed)
+        .map_err(|_| Error::MalformedField(field, url.clone()))?
+        .into();
+    Ok(())
+}
+
 #[derive(Debug, PartialEq, Deserialize)]
 pub struct SpecVersion {
     pub major: u32,
@@ -231,6 +510,19 @@ where
     ServiceOuter::deserialize(d).map(|s| s.service)
 }
 
+#[derive(Debug, PartialEq, Deserialize)]
+struct IconOuter {
+    icon: Vec<Icon>,
+}
+
+/// Flatten the `icon` list down
+fn deserialize_icons<'de, D>(d: D) -> Result<Vec<Icon>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    IconOuter::deserialize(d).map(|i| i.icon)
+}
+
 #[derive(Debug, PartialEq, Deserialize)]
 struct DeviceOuter {
     device: Vec<Device>,
@@ -244,11 +536,180 @@ where
     DeviceOuter::deserialize(d).map(|d| d.device)
 }
 
+/// A description document, along with what the HTTP response said about it
+#[derive(Debug)]
+pub struct Described {
+    pub description: Description,
+    /// Where the document was retrieved from, after following any redirects.
+    /// Relative URLs in the document were resolved against this.
+    pub location: Url,
+    /// Such as `Linux/5.4 UPnP/1.1 MiniUPnPd/2.1`
+    pub server: Option<String>,
+    /// The language of user-facing fields like the friendly name
+    pub content_language: Option<String>,
+    /// The CONFIGID.UPNP.ORG header
+    pub config_id: Option<i32>,
+}
+
+impl Described {
+    /// Whether this description agrees with the CONFIGID.UPNP.ORG a device announced over SSDP.
+    /// If it doesn't, the description is stale or the device is misbehaving.
+    /// Values that weren't given on either side can't disagree.
+    pub fn config_id_matches(&self, ssdp: Option<i32>) -> bool {
+        let ssdp = match ssdp {
+            Some(ssdp) => ssdp,
+            None => return true,
+        };
+        let document = self
+            .description
+            .config_id
+            .as_ref()
+            .and_then(|c| c.trim().parse::<i32>().ok());
+
+        self.config_id.is_none_or(|c| c == ssdp) && document.is_none_or(|c| c == ssdp)
+    }
+
+    /// The config ID from the header, or failing that the document
+    fn any_config_id(&self) -> Option<i32> {
+        self.config_id.or_else(|| {
+            self.description
+                .config_id
+                .as_ref()
+                .and_then(|c| c.trim().parse().ok())
+        })
+    }
+
+    fn from_response(response: &Response) -> Result<Self, Error> {
+        let mut description = Description::from_slice(&response.body)?;
+        description.resolve_urls(&response.url)?;
+
+        // These are only metadata, so a device getting one wrong shouldn't spoil the description
+        let header = |name: &str| {
+            let value = response.headers.get(name)?.to_str().ok()?;
+            Some(value.trim().to_owned())
+        };
+
+        Ok(Self {
+            server: header("server"),
+            content_language: header("content-language"),
+            config_id: header("configid.upnp.org").and_then(|c| c.parse().ok()),
+            location: response.url.clone(),
+            description,
+        })
+    }
+}
+
 /// Retrieve and parse a device description.
 /// See the location field from [discovery::Device](../discovery/struct.Device.html#structfield.location).
-pub async fn describe(location: &str) -> Result<Device, Error> {
-    let body = reqwest::get(location).await?.text().await?;
+pub async fn describe(location: &str) -> Result<Described, Error> {
+    describe_with(&HttpFetcher::default(), location).await
+}
+
+/// Like [describe](fn.describe.html), but retrieve the document with your own client
+pub async fn describe_with<F: Fetch + ?Sized>(
+    fetcher: &F,
+    location: &str,
+) -> Result<Described, Error> {
+    let response = fetch::get(fetcher, &Url::parse(location)?, &Headers::new()).await?;
+    Described::from_response(&response)
+}
+
+/// Parse a description saved to disk.
+/// Relative URLs are resolved against its `file://` URL, so they won't be fetched.
+/// Files larger than the default [max_size](struct.Limits.html#structfield.max_size) are refused.
+pub async fn describe_file(path: impl AsRef<Path>) -> Result<Described, Error> {
+    use tokio::io::AsyncReadExt;
+
+    let path = tokio::fs::canonicalize(path).await?;
+    let url = Url::from_file_path(&path)
+        .map_err(|_| Error::MalformedField("path", path.display().to_string()))?;
+
+    let max_size = Limits::default().max_size;
+    let mut body = Vec::new();
+    tokio::fs::File::open(&path)
+        .await?
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut body)
+        .await?;
+    if body.len() > max_size {
+        return Err(Error::TooLarge(max_size));
+    }
+
+    let response = Response {
+        url,
+        status: 200,
+        headers: Headers::new(),
+        body: body.into(),
+    };
+    Described::from_response(&response)
+}
+
+/// The description of devices found by discovery
+#[derive(Debug)]
+pub struct DescribedDevice {
+    /// The location the description was retrieved from
+    pub location: String,
+    /// Every discovered device with this location.
+    /// Usually a root device and the devices embedded in it.
+    pub discovered: Vec<discovery::Device>,
+    pub description: Result<Described, Error>,
+}
+
+/// Describe devices found by [discovery](../discovery/struct.Discovery.html#method.find),
+/// retrieving each location once, and at most `concurrency` at a time.
+/// A device that can't be described doesn't stop the others.
+/// Locations that aren't http or https URLs aren't fetched, and fail with
+/// [UnsupportedScheme](../enum.Error.html#variant.UnsupportedScheme).
+pub async fn describe_all<F: Fetch + ?Sized>(
+    fetcher: &F,
+    devices: Vec<discovery::Device>,
+    concurrency: usize,
+) -> Vec<DescribedDevice> {
+    let mut by_location: Vec<(String, Vec<discovery::Device>)> = Vec::new();
+    for device in devices {
+        match by_location.iter_mut().find(|(l, _)| *l == device.location) {
+            Some((_, discovered)) => discovered.push(device),
+            None => by_location.push((device.location.clone(), vec![device])),
+        }
+    }
+
+    stream::iter(by_location)
+        .map(|(location, discovered)| async move {
+            DescribedDevice {
+                description: describe_with(fetcher, &location).await,
+                location,
+                discovered,
+            }
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Retrieve an icon's image data.
+/// Use [Device::icon_for](struct.Device.html#method.icon_for) to pick one.
+pub async fn fetch_icon(icon: &Icon) -> Result<Bytes, Error> {
+    fetch_icon_with(&HttpFetcher::default(), icon).await
+}
+
+/// Like [fetch_icon](fn.fetch_icon.html), but with your own client
+pub async fn fetch_icon_with<F: Fetch + ?Sized>(fetcher: &F, icon: &Icon) -> Result<Bytes, Error> {
+    let url = Url::parse(&icon.url)?;
+    Ok(fetch::get(fetcher, &url, &Headers::new()).await?.body)
+}
+
+/// Retrieve and parse the description (SCPD) of a service's actions and state variables.
+/// The service should come from [describe](fn.describe.html), so its URLs are absolute.
+pub async fn describe_service(service: &Service) -> Result<ServiceDescription, Error> {
+    describe_service_with(&HttpFetcher::default(), service).await
+}
 
-    let document: Description = serde_xml_rs::from_str(&body)?;
-    Ok(document.device)
+/// Like [describe_service](fn.describe_service.html), but with your own client
+pub async fn describe_service_with<F: Fetch + ?Sized>(
+    fetcher: &F,
+    service: &Service,
+) -> Result<ServiceDescription, Error> {
+    let url = Url::parse(&service.scpd_url)?;
+    let response = fetch::get(fetcher, &url, &Headers::new()).await?;
+    ServiceDescription::from_slice(&response.body)
 }
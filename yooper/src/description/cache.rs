@@ -0,0 +1,295 @@
+//! Remembering descriptions between discovery passes
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use url::Url;
+use xml::writer::{EmitterConfig, XmlEvent};
+
+use super::{extensions, fetch, Described, Fetch, HttpFetcher, Response, UniqueDeviceName};
+use crate::ssdp::message::Message;
+use crate::ssdp::packet::Headers;
+use crate::Error;
+
+/// Caches descriptions by location, so devices seen again needn't be described again.
+///
+/// Entries go stale when SSDP reports a new BOOTID.UPNP.ORG or CONFIGID.UPNP.ORG for them:
+/// pass every message you receive to [notice](#method.notice).
+/// Stale entries are revalidated with `If-None-Match` and `If-Modified-Since`
+/// if the device sent an `ETag` or `Last-Modified`, and otherwise fetched again.
+pub struct Cache<F = HttpFetcher> {
+    fetcher: F,
+    entries: HashMap<String, Entry>,
+}
+
+struct Entry {
+    response: Response,
+    described: Arc<Described>,
+    boot_id: Option<i32>,
+    config_id: Option<i32>,
+    stale: bool,
+}
+
+impl Entry {
+    fn new(response: Response, boot_id: Option<i32>) -> Result<Self, Error> {
+        let described = Described::from_response(&response)?;
+        Ok(Self {
+            config_id: described.any_config_id(),
+            described: Arc::new(described),
+            response,
+            boot_id,
+            stale: false,
+        })
+    }
+
+    /// Headers to make a request that only returns a body if it's changed
+    fn conditional_headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        if let Some(etag) = self.response.headers.get("etag") {
+            headers.insert("If-None-Match", etag.clone());
+        }
+        if let Some(modified) = self.response.headers.get("last-modified") {
+            headers.insert("If-Modified-Since", modified.clone());
+        }
+        headers
+    }
+
+    /// Take note of the IDs a device announced, going stale if they've changed
+    fn observe(&mut self, boot_id: Option<i32>, config_id: Option<i32>) {
+        fn changed(known: &mut Option<i32>, seen: Option<i32>) -> bool {
+            match (*known, seen) {
+                (Some(k), Some(s)) if k != s => {
+                    *known = Some(s);
+                    true
+                }
+                (None, Some(_)) => {
+                    *known = seen;
+                    false
+                }
+                _ => false,
+            }
+        }
+
+        let boot = changed(&mut self.boot_id, boot_id);
+        let config = changed(&mut self.config_id, config_id);
+        self.stale |= boot || config;
+    }
+}
+
+impl Cache {
+    pub fn new() -> Self {
+        Self::with_fetcher(HttpFetcher::default())
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F> Cache<F> {
+    /// Retrieve documents with your own client
+    pub fn with_fetcher(fetcher: F) -> Self {
+        Self {
+            fetcher,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn fetcher(&self) -> &F {
+        &self.fetcher
+    }
+
+    /// The cached description of the root device with this UDN, if there is one.
+    /// It may be stale.
+    pub fn get(&self, udn: &UniqueDeviceName) -> Option<Arc<Described>> {
+        self.entries
+            .values()
+            .find(|e| e.described.description.device.unique_device_name == *udn)
+            .map(|e| e.described.clone())
+    }
+
+    /// Make the next [describe](#method.describe) of `location` check with the device
+    pub fn invalidate(&mut self, location: &str) {
+        if let Some(entry) = self.entries.get_mut(location) {
+            entry.stale = true;
+        }
+    }
+
+    /// Update the cache from an SSDP message.
+    /// A new boot or config ID makes that location stale,
+    /// and a device leaving the network is forgotten.
+    pub fn notice(&mut self, message: &Message) {
+        match message {
+            Message::Available(m) => self.observe(&m.location, m.boot_id, m.config_id),
+            Message::SearchResponse(m) => self.observe(&m.location, m.boot_id, m.config_id),
+            Message::Update(m) => {
+                self.observe(&m.location, m.next_boot_id, m.config_id);
+                self.invalidate(&m.location);
+            }
+            Message::Unavailable(m) => {
+                let uuid = &m.unique_service_name.uuid;
+                self.entries
+                    .retain(|_, e| e.described.description.device.unique_device_name.uuid != *uuid);
+            }
+            Message::MSearch(_) => (),
+        }
+    }
+
+    fn observe(&mut self, location: &str, boot_id: Option<i32>, config_id: Option<i32>) {
+        if let Some(entry) = self.entries.get_mut(location) {
+            entry.observe(boot_id, config_id);
+        }
+    }
+
+    /// Write the cache to disk, to [load](#method.load) next time
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        let mut w = EmitterConfig::new()
+            .perform_indent(true)
+            .create_writer(&mut buf);
+
+        w.write(XmlEvent::start_element("descriptionCache"))?;
+        for (location, entry) in &self.entries {
+            // Entries that aren't text can't go in the file; they'll just be fetched again
+            let headers: Result<Vec<_>, _> = entry
+                .response
+                .headers
+                .iter()
+                .map(|(k, v)| Ok::<_, Error>((k.to_str()?, v.to_str()?)))
+                .collect();
+            let (headers, body) = match (headers, std::str::from_utf8(&entry.response.body)) {
+                (Ok(headers), Ok(body)) => (headers, body),
+                _ => continue,
+            };
+
+            let status = entry.response.status.to_string();
+            let boot_id = entry.boot_id.map(|b| b.to_string());
+            let config_id = entry.config_id.map(|c| c.to_string());
+
+            let mut start = XmlEvent::start_element("entry")
+                .attr("location", location)
+                .attr("url", entry.response.url.as_str())
+                .attr("status", &status);
+            if let Some(boot_id) = &boot_id {
+                start = start.attr("bootId", boot_id);
+            }
+            if let Some(config_id) = &config_id {
+                start = start.attr("configId", config_id);
+            }
+            w.write(start)?;
+
+            for (k, v) in headers {
+                w.write(XmlEvent::start_element("header").attr("name", k))?;
+                w.write(XmlEvent::characters(v))?;
+                w.write(XmlEvent::end_element())?;
+            }
+            w.write(XmlEvent::start_element("body"))?;
+            w.write(XmlEvent::characters(body))?;
+            w.write(XmlEvent::end_element())?;
+
+            w.write(XmlEvent::end_element())?;
+        }
+        w.write(XmlEvent::end_element())?;
+
+        Ok(fs::write(path, buf)?)
+    }
+
+    /// Add the entries from a file written by [save](#method.save), if it exists.
+    /// They might have changed since, so they're all checked with their devices when next used.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let body = match fs::read(path) {
+            Ok(body) => body,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let root = match extensions::read_document(&body, true)? {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+
+        for element in root.children.iter().filter(|c| c.name == "entry") {
+            let attribute = |name: &'static str| {
+                element
+                    .attribute(name)
+                    .ok_or_else(|| Error::ParseFailure(format!("cache entry without {}", name)))
+            };
+            let number = |name: &'static str| {
+                element
+                    .attribute(name)
+                    .map(|v| {
+                        v.parse::<i32>()
+                            .map_err(|_| Error::MalformedField(name, v.to_owned()))
+                    })
+                    .transpose()
+            };
+
+            let response = Response {
+                url: Url::parse(attribute("url")?)?,
+                status: number("status")?
+                    .map(|v| {
+                        u16::try_from(v).map_err(|_| Error::MalformedField("status", v.to_string()))
+                    })
+                    .transpose()?
+                    .unwrap_or(200),
+                headers: element
+                    .children
+                    .iter()
+                    .filter(|c| c.name == "header")
+                    .filter_map(|c| Some((c.attribute("name")?.to_owned(), c.text.clone())))
+                    .collect(),
+                body: element
+                    .children
+                    .iter()
+                    .find(|c| c.name == "body")
+                    .map_or(Bytes::new(), |c| c.text.clone().into()),
+            };
+
+            let mut entry = Entry::new(response, number("bootId")?)?;
+            if let Some(config_id) = number("configId")? {
+                entry.config_id = Some(config_id);
+            }
+            entry.stale = true;
+            self.entries
+                .insert(attribute("location")?.to_owned(), entry);
+        }
+        Ok(())
+    }
+}
+
+impl<F: Fetch> Cache<F> {
+    /// Like [describe](fn.describe.html), but only retrieves the document
+    /// if it isn't cached or the cached copy is stale
+    pub async fn describe(&mut self, location: &str) -> Result<Arc<Described>, Error> {
+        let headers = match self.entries.get(location) {
+            Some(entry) if !entry.stale => return Ok(entry.described.clone()),
+            Some(entry) => entry.conditional_headers(),
+            None => Headers::new(),
+        };
+
+        let response = fetch::get(&self.fetcher, &Url::parse(location)?, &headers).await?;
+
+        if response.status == 304 {
+            if let Some(entry) = self.entries.get_mut(location) {
+                for name in &["etag", "last-modified"] {
+                    if let Some(v) = response.headers.get(*name) {
+                        entry.response.headers.insert(*name, v.clone());
+                    }
+                }
+                entry.stale = false;
+                return Ok(entry.described.clone());
+            }
+        }
+
+        let boot_id = self.entries.get(location).and_then(|e| e.boot_id);
+        let entry = Entry::new(response, boot_id)?;
+        let described = entry.described.clone();
+        self.entries.insert(location.to_owned(), entry);
+        Ok(described)
+    }
+}
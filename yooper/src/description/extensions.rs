@@ -0,0 +1,225 @@
+//! Elements vendors add to descriptions beyond what UPnP specifies,
+//! like `<dlna:X_DLNADOC>` or Sonos' `<roomName>`
+use std::io::{Read, Write};
+
+use xml::reader::{self, EventReader, ParserConfig};
+use xml::writer::{self, EventWriter};
+
+use super::{Device, DEVICE_NAMESPACE};
+use crate::Error;
+
+/// Deeper than any real document, so a hostile one can't exhaust the stack
+const MAX_DEPTH: usize = 64;
+
+/// Children of `<device>` that UPnP specifies
+const DEVICE_FIELDS: &[&str] = &[
+    "deviceType",
+    "friendlyName",
+    "manufacturer",
+    "manufacturerURL",
+    "modelDescription",
+    "modelName",
+    "modelNumber",
+    "modelURL",
+    "serialNumber",
+    "UDN",
+    "UPC",
+    "iconList",
+    "serviceList",
+    "deviceList",
+    "presentationURL",
+];
+
+/// Children of `<service>` that UPnP specifies
+const SERVICE_FIELDS: &[&str] = &[
+    "serviceType",
+    "serviceId",
+    "SCPDURL",
+    "controlURL",
+    "eventSubURL",
+];
+
+/// An element in a description that isn't part of the UPnP schema
+#[derive(Debug, PartialEq, Clone)]
+pub struct Extension {
+    /// The local name, like `X_DLNADOC`
+    pub name: String,
+    /// Like `urn:schemas-dlna-org:device-1-0`.
+    /// Elements vendors add without a prefix are usually in the UPnP namespace.
+    pub namespace: Option<String>,
+    /// The prefix the document used, like `dlna`, so it can be written back the same way
+    pub prefix: Option<String>,
+    /// Attributes by local name, in document order
+    pub attributes: Vec<(String, String)>,
+    /// Text content. In descriptions, surrounding whitespace is trimmed
+    pub text: String,
+    pub children: Vec<Extension>,
+}
+
+impl Extension {
+    /// Whether this is `name` in the namespace `namespace`
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        self.name == name && self.namespace.as_deref() == Some(namespace)
+    }
+
+    /// The value of an attribute
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn is_upnp(&self) -> bool {
+        self.namespace
+            .as_deref()
+            .is_none_or(|ns| ns == DEVICE_NAMESPACE)
+    }
+
+    /// Read the rest of an element whose start tag has just been read,
+    /// `depth` elements down from the root
+    fn read<R: Read>(
+        reader: &mut EventReader<R>,
+        name: xml::name::OwnedName,
+        attributes: Vec<xml::attribute::OwnedAttribute>,
+        depth: usize,
+    ) -> Result<Self, Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::ParseFailure(format!(
+                "elements are nested more than {} deep",
+                MAX_DEPTH
+            )));
+        }
+        let mut element = Self {
+            name: name.local_name,
+            namespace: name.namespace,
+            prefix: name.prefix,
+            attributes: attributes
+                .into_iter()
+                .map(|a| (a.name.local_name, a.value))
+                .collect(),
+            text: String::new(),
+            children: vec![],
+        };
+
+        loop {
+            match reader.next()? {
+                reader::XmlEvent::StartElement {
+                    name, attributes, ..
+                } => element
+                    .children
+                    .push(Self::read(reader, name, attributes, depth + 1)?),
+                reader::XmlEvent::Characters(text) | reader::XmlEvent::Whitespace(text) => {
+                    element.text.push_str(&text)
+                }
+                reader::XmlEvent::EndElement { .. } => return Ok(element),
+                _ => (),
+            }
+        }
+    }
+
+    pub(super) fn write_xml<W: Write>(&self, w: &mut EventWriter<W>) -> Result<(), Error> {
+        let name = match (&self.namespace, &self.prefix) {
+            (Some(ns), prefix) => xml::name::Name::qualified(&self.name, ns, prefix.as_deref()),
+            (None, _) => xml::name::Name::local(&self.name),
+        };
+        let mut start = writer::XmlEvent::start_element(name);
+        start = match (&self.namespace, &self.prefix) {
+            (Some(ns), Some(prefix)) => start.ns(prefix.as_str(), ns.as_str()),
+            (Some(ns), None) => start.default_ns(ns.as_str()),
+            (None, _) => start,
+        };
+        for (k, v) in &self.attributes {
+            start = start.attr(k.as_str(), v);
+        }
+        w.write(start)?;
+
+        if !self.text.is_empty() {
+            w.write(writer::XmlEvent::characters(&self.text))?;
+        }
+        for child in &self.children {
+            child.write_xml(w)?;
+        }
+        w.write(writer::XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Read a whole document into a tree, or None if it's empty.
+/// If `trim`, whitespace around text is dropped.
+pub(crate) fn read_document(body: &[u8], trim: bool) -> Result<Option<Extension>, Error> {
+    let mut reader = ParserConfig::new()
+        .trim_whitespace(trim)
+        .cdata_to_characters(true)
+        .create_reader(body);
+
+    loop {
+        match reader.next()? {
+            reader::XmlEvent::StartElement {
+                name, attributes, ..
+            } => return Extension::read(&mut reader, name, attributes, 0).map(Some),
+            reader::XmlEvent::EndDocument => return Ok(None),
+            _ => (),
+        }
+    }
+}
+
+/// Find the extensions in a description document,
+/// and attach them to the matching parts of the already-parsed root device
+pub(super) fn capture(body: &[u8], device: &mut Device) -> Result<(), Error> {
+    let root = match read_document(body, true)? {
+        Some(root) => root,
+        None => return Ok(()),
+    };
+
+    if let Some(element) = root
+        .children
+        .iter()
+        .find(|c| c.is_upnp() && c.name == "device")
+    {
+        attach(element, device);
+    }
+    Ok(())
+}
+
+/// Devices and services are matched up in document order
+fn attach(element: &Extension, device: &mut Device) {
+    let mut services = device.services.iter_mut();
+    let mut devices = device.devices.iter_mut();
+
+    for child in &element.children {
+        if !child.is_upnp() || !DEVICE_FIELDS.contains(&child.name.as_str()) {
+            device.extensions.push(child.clone());
+            continue;
+        }
+
+        match child.name.as_str() {
+            "serviceList" => {
+                for (service, element) in (&mut services).zip(upnp_children(child, "service")) {
+                    service.extensions = element
+                        .children
+                        .iter()
+                        .filter(|c| !c.is_upnp() || !SERVICE_FIELDS.contains(&c.name.as_str()))
+                        .cloned()
+                        .collect();
+                }
+            }
+            "deviceList" => {
+                for (device, element) in (&mut devices).zip(upnp_children(child, "device")) {
+                    attach(element, device);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn upnp_children<'a>(
+    element: &'a Extension,
+    name: &'a str,
+) -> impl Iterator<Item = &'a Extension> + 'a {
+    element
+        .children
+        .iter()
+        .filter(move |c| c.is_upnp() && c.name == name)
+}
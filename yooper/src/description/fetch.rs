@@ -0,0 +1,492 @@
+//! Retrieving description documents, with whatever HTTP client you like
+use bytes::{Bytes, BytesMut};
+use futures::future::{BoxFuture, FutureExt};
+use tokio::time::{self, Duration};
+use url::Url;
+
+use crate::ssdp::packet::{HeaderName, HeaderValue, Headers};
+use crate::Error;
+
+/// A retrieved document
+#[derive(Debug, Clone)]
+pub struct Response {
+    /// Where the document came from, after following any redirects
+    pub url: Url,
+    /// 200, or 304 if the request was conditional and the document hasn't changed
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Bytes,
+}
+
+/// Retrieves the body of a document over HTTP.
+///
+/// [HttpFetcher](struct.HttpFetcher.html) is what [describe](fn.describe.html) uses.
+/// It's also implemented for [reqwest::Client](https://docs.rs/reqwest/0.10/reqwest/struct.Client.html),
+/// without any limits, or implement it yourself to bring a different client.
+pub trait Fetch: Send + Sync {
+    /// Retrieve `url`, following redirects, sending any extra `headers` such as `If-None-Match`.
+    /// Statuses other than success and 304 Not Modified should be errors.
+    fn fetch<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+    ) -> BoxFuture<'a, Result<Response, Error>>;
+}
+
+/// Sends a request with a body, as [control](../control/index.html) does.
+///
+/// Unlike [Fetch](trait.Fetch.html), responses with any status should be returned, not errors,
+/// since SOAP faults come back with a 500.
+pub trait Post: Send + Sync {
+    fn post<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+        body: Bytes,
+    ) -> BoxFuture<'a, Result<Response, Error>>;
+}
+
+/// Bounds on retrieving a document, so a wedged or malicious device can't hang or flood you
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// How long to wait to connect
+    pub connect_timeout: Duration,
+    /// How long to wait for the response to start, and for each part of the body after that
+    pub read_timeout: Duration,
+    /// How long to wait for the whole body, however steadily it arrives
+    pub body_timeout: Duration,
+    /// The largest document to accept, in bytes
+    pub max_size: usize,
+    /// How many more times to try after a timeout, failure to connect, or server error
+    pub retries: u32,
+    /// How long to wait before the first retry. Doubles after each one
+    pub backoff: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(10),
+            body_timeout: Duration::from_secs(30),
+            max_size: 1024 * 1024,
+            retries: 2,
+            backoff: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Retrieves documents with reqwest, within some [Limits](struct.Limits.html)
+#[derive(Debug, Clone)]
+pub struct HttpFetcher {
+    client: reqwest::Client,
+    limits: Limits,
+}
+
+impl HttpFetcher {
+    pub fn new(limits: Limits) -> Result<Self, Error> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(limits.connect_timeout)
+            .build()?;
+        Ok(Self::with_client(client, limits))
+    }
+
+    /// Use a client you've configured, with your proxy and TLS settings.
+    /// `limits.connect_timeout` is ignored, set it on the client instead.
+    pub fn with_client(client: reqwest::Client, limits: Limits) -> Self {
+        Self { client, limits }
+    }
+
+    async fn fetch_once(&self, url: &Url, headers: &Headers) -> Result<Response, Error> {
+        let response = time::timeout(
+            self.limits.read_timeout,
+            request(&self.client, url, headers),
+        )
+        .await
+        .map_err(|_| Error::Timeout("waiting for a response"))??;
+
+        self.read(response).await
+    }
+
+    /// Read the body of a response, within our limits
+    async fn read(&self, mut response: reqwest::Response) -> Result<Response, Error> {
+        let limits = &self.limits;
+        if response
+            .content_length()
+            .is_some_and(|len| len > limits.max_size as u64)
+        {
+            return Err(Error::TooLarge(limits.max_size));
+        }
+
+        let url = response.url().clone();
+        let status = response.status().as_u16();
+        let headers = response_headers(&response);
+        let body = time::timeout(limits.body_timeout, read_body(&mut response, limits))
+            .await
+            .map_err(|_| Error::Timeout("reading the whole response"))??;
+
+        Ok(Response {
+            url,
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+async fn read_body(response: &mut reqwest::Response, limits: &Limits) -> Result<Bytes, Error> {
+    let mut body = BytesMut::new();
+    while let Some(chunk) = time::timeout(limits.read_timeout, response.chunk())
+        .await
+        .map_err(|_| Error::Timeout("reading the response"))?
+        .map_err(transport_error)?
+    {
+        if body.len() + chunk.len() > limits.max_size {
+            return Err(Error::TooLarge(limits.max_size));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+impl Default for HttpFetcher {
+    /// Panics if the HTTP client can't be created, like `reqwest::Client::new`
+    fn default() -> Self {
+        Self::new(Limits::default()).expect("Couldn't create an HTTP client")
+    }
+}
+
+impl Fetch for HttpFetcher {
+    fn fetch<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        async move {
+            let mut backoff = self.limits.backoff;
+            let mut retries = self.limits.retries;
+            loop {
+                match self.fetch_once(url, headers).await {
+                    Err(e) if retries > 0 && is_transient(&e) => {
+                        time::delay_for(backoff).await;
+                        backoff *= 2;
+                        retries -= 1;
+                    }
+                    result => return result,
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Requests with bodies aren't retried, since they may not be safe to repeat
+impl Post for HttpFetcher {
+    fn post<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+        body: Bytes,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        async move {
+            let response = time::timeout(
+                self.limits.read_timeout,
+                post(&self.client, url, headers, body),
+            )
+            .await
+            .map_err(|_| Error::Timeout("waiting for a response"))??;
+
+            self.read(response).await
+        }
+        .boxed()
+    }
+}
+
+/// So one client can be shared, say with a [ManagedMapping](../igd/struct.ManagedMapping.html)'s task
+impl<T: Post + ?Sized> Post for std::sync::Arc<T> {
+    fn post<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+        body: Bytes,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        (**self).post(url, headers, body)
+    }
+}
+
+impl Fetch for reqwest::Client {
+    fn fetch<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        async move {
+            let response = request(self, url, headers).await?;
+            Ok(Response {
+                url: response.url().clone(),
+                status: response.status().as_u16(),
+                headers: response_headers(&response),
+                body: response.bytes().await.map_err(transport_error)?,
+            })
+        }
+        .boxed()
+    }
+}
+
+impl Post for reqwest::Client {
+    fn post<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+        body: Bytes,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        async move {
+            let response = post(self, url, headers, body).await?;
+            Ok(Response {
+                url: response.url().clone(),
+                status: response.status().as_u16(),
+                headers: response_headers(&response),
+                body: response.bytes().await.map_err(transport_error)?,
+            })
+        }
+        .boxed()
+    }
+}
+
+async fn post(
+    client: &reqwest::Client,
+    url: &Url,
+    headers: &Headers,
+    body: Bytes,
+) -> Result<reqwest::Response, Error> {
+    let mut request = client.post(url.clone()).body(body);
+    for (k, v) in headers.iter() {
+        request = request.header(k.as_bytes(), v.as_bytes());
+    }
+    request.send().await.map_err(transport_error)
+}
+
+/// Send a request, and make sure it was successful
+async fn request(
+    client: &reqwest::Client,
+    url: &Url,
+    headers: &Headers,
+) -> Result<reqwest::Response, Error> {
+    let mut request = client.get(url.clone());
+    for (k, v) in headers.iter() {
+        request = request.header(k.as_bytes(), v.as_bytes());
+    }
+    let response = request.send().await.map_err(transport_error)?;
+
+    let status = response.status();
+    if status.is_success() || status == reqwest::StatusCode::NOT_MODIFIED {
+        Ok(response)
+    } else {
+        Err(Error::HTTPStatus(status.as_u16()))
+    }
+}
+
+fn response_headers(response: &reqwest::Response) -> Headers {
+    response
+        .headers()
+        .iter()
+        .map(|(k, v)| {
+            (
+                HeaderName::from(k.as_str().to_owned()),
+                HeaderValue::from_bytes(Bytes::copy_from_slice(v.as_bytes())),
+            )
+        })
+        .collect()
+}
+
+fn transport_error(e: reqwest::Error) -> Error {
+    if e.is_timeout() && e.is_connect() {
+        Error::Timeout("connecting")
+    } else if e.is_timeout() {
+        Error::Timeout("waiting for a response")
+    } else if e.is_connect() {
+        Error::ConnectFailed(e)
+    } else {
+        Error::HTTPError(e)
+    }
+}
+
+/// Whether trying again might help
+fn is_transient(e: &Error) -> bool {
+    match e {
+        Error::Timeout(_) | Error::ConnectFailed(_) => true,
+        Error::HTTPStatus(status) => *status >= 500,
+        _ => false,
+    }
+}
+
+/// Retrieve `url` with `fetcher`.
+/// URLs come from devices, so anything but http and https is refused,
+/// lest a device point us at a local file.
+pub(super) async fn get<F: Fetch + ?Sized>(
+    fetcher: &F,
+    url: &Url,
+    headers: &Headers,
+) -> Result<Response, Error> {
+    check_scheme(url)?;
+    fetcher.fetch(url, headers).await
+}
+
+pub(crate) fn check_scheme(url: &Url) -> Result<(), Error> {
+    match url.scheme() {
+        "http" | "https" => Ok(()),
+        _ => Err(Error::UnsupportedScheme(url.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    enum Reply {
+        Send(&'static str),
+        /// Accept the request, but never answer
+        Hang,
+        /// Send the headers, then the body a byte at a time
+        Trickle(&'static str, &'static str),
+    }
+
+    /// Serve one reply per connection, in order. Returns the URL and a count of requests
+    fn serve(replies: Vec<Reply>) -> (Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/desc.xml",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let requests = count.clone();
+        std::thread::spawn(move || {
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let requests = requests.clone();
+                // Each on its own thread, so a hung connection doesn't hold up the next
+                std::thread::spawn(move || {
+                    let mut buf = [0; 1024];
+                    let _ = stream.read(&mut buf);
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    match reply {
+                        Reply::Send(response) => {
+                            let _ = stream.write_all(response.as_bytes());
+                        }
+                        Reply::Hang => std::thread::sleep(std::time::Duration::from_secs(2)),
+                        Reply::Trickle(head, body) => {
+                            let _ = stream.write_all(head.as_bytes());
+                            for byte in body.as_bytes().chunks(1) {
+                                std::thread::sleep(std::time::Duration::from_millis(100));
+                                if stream.write_all(byte).is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        (url, count)
+    }
+
+    fn fetcher() -> HttpFetcher {
+        HttpFetcher::new(Limits {
+            read_timeout: Duration::from_millis(200),
+            max_size: 16,
+            retries: 2,
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello";
+    const UNAVAILABLE: &str =
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let (url, _) = serve(vec![Reply::Send(OK)]);
+        let response = fetcher().fetch(&url, &Headers::new()).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_too_large() {
+        let (url, _) = serve(vec![Reply::Send(
+            "HTTP/1.1 200 OK\r\nContent-Length: 100\r\nConnection: close\r\n\r\n",
+        )]);
+        match fetcher().fetch(&url, &Headers::new()).await.unwrap_err() {
+            Error::TooLarge(16) => (),
+            e => panic!("Didn't get the error we assumed! {:?}", e),
+        }
+
+        // Without a length, we find out as we go
+        let (url, _) = serve(vec![Reply::Send(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n0123456789abcdefghijklmnopqrstuvwxyz",
+        )]);
+        match fetcher().fetch(&url, &Headers::new()).await.unwrap_err() {
+            Error::TooLarge(16) => (),
+            e => panic!("Didn't get the error we assumed! {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        let (url, count) = serve(vec![Reply::Send(UNAVAILABLE), Reply::Hang, Reply::Send(OK)]);
+        let response = fetcher().fetch(&url, &Headers::new()).await.unwrap();
+        assert_eq!(response.body, "hello");
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up() {
+        let (url, count) = serve(vec![Reply::Hang, Reply::Hang, Reply::Hang]);
+        match fetcher().fetch(&url, &Headers::new()).await.unwrap_err() {
+            Error::Timeout("waiting for a response") => (),
+            e => panic!("Didn't get the error we assumed! {:?}", e),
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_body_timeout() {
+        // Each byte comes well within the read timeout, but the whole body takes too long
+        let (url, _) = serve(vec![Reply::Trickle(
+            "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n",
+            "0123456789",
+        )]);
+        let fetcher = HttpFetcher::new(Limits {
+            body_timeout: Duration::from_millis(500),
+            retries: 0,
+            ..fetcher().limits
+        })
+        .unwrap();
+        match fetcher.fetch(&url, &Headers::new()).await.unwrap_err() {
+            Error::Timeout("reading the whole response") => (),
+            e => panic!("Didn't get the error we assumed! {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_final() {
+        let (url, count) = serve(vec![
+            Reply::Send("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+            Reply::Send(OK),
+        ]);
+        match fetcher().fetch(&url, &Headers::new()).await.unwrap_err() {
+            Error::HTTPStatus(404) => (),
+            e => panic!("Didn't get the error we assumed! {:?}", e),
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,330 @@
+//! The service description (SCPD) document, listing a service's actions and state variables
+use serde::{Deserialize, Deserializer};
+use serde_with::rust::display_fromstr;
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::SpecVersion;
+use crate::Error;
+
+/// The actions and state variables of a service
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceDescription {
+    pub spec_version: SpecVersion,
+    /// Absent for services with no actions
+    #[serde(deserialize_with = "deserialize_actions", default)]
+    pub action_list: Vec<Action>,
+    #[serde(deserialize_with = "deserialize_state_variables")]
+    pub service_state_table: Vec<StateVariable>,
+}
+
+impl ServiceDescription {
+    /// Parse a document you already have, such as one saved to disk
+    pub fn from_slice(body: &[u8]) -> Result<Self, Error> {
+        Ok(serde_xml_rs::from_reader(body)?)
+    }
+
+    pub fn action(&self, name: &str) -> Option<&Action> {
+        self.action_list.iter().find(|a| a.name == name)
+    }
+
+    pub fn state_variable(&self, name: &str) -> Option<&StateVariable> {
+        self.service_state_table.iter().find(|v| v.name == name)
+    }
+}
+
+impl FromStr for ServiceDescription {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(serde_xml_rs::from_str(s)?)
+    }
+}
+
+/// Something a control point can ask a service to do
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Action {
+    pub name: String,
+    /// In-arguments come before out-arguments, in the order they should be sent
+    #[serde(deserialize_with = "deserialize_arguments", default)]
+    pub argument_list: Vec<Argument>,
+}
+
+impl Action {
+    /// Arguments the control point sends
+    pub fn in_arguments(&self) -> impl Iterator<Item = &Argument> {
+        self.argument_list
+            .iter()
+            .filter(|a| a.direction == Direction::In)
+    }
+
+    /// Arguments the service returns
+    pub fn out_arguments(&self) -> impl Iterator<Item = &Argument> {
+        self.argument_list
+            .iter()
+            .filter(|a| a.direction == Direction::Out)
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Argument {
+    pub name: String,
+    #[serde(with = "display_fromstr")]
+    pub direction: Direction,
+    /// Whether this out-argument is the action's return value
+    #[serde(deserialize_with = "deserialize_flag", default)]
+    pub retval: bool,
+    /// The state variable that defines this argument's type
+    pub related_state_variable: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::In => "in",
+            Self::Out => "out",
+        })
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "in" => Ok(Self::In),
+            "out" => Ok(Self::Out),
+            _ => Err(Error::UnexpectedValue("Direction", s.to_owned())),
+        }
+    }
+}
+
+/// A piece of the service's state, and the type of any arguments related to it
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateVariable {
+    pub name: String,
+    #[serde(with = "display_fromstr")]
+    pub data_type: DataType,
+    pub default_value: Option<String>,
+    /// If not empty, the only values this variable may take
+    #[serde(
+        rename = "allowedValueList",
+        deserialize_with = "deserialize_allowed_values",
+        default
+    )]
+    pub allowed_values: Vec<String>,
+    #[serde(rename = "allowedValueRange")]
+    pub allowed_range: Option<AllowedValueRange>,
+    /// Whether changes are sent to event subscribers
+    #[serde(
+        rename = "sendEvents",
+        deserialize_with = "deserialize_yes_no",
+        default = "default_true"
+    )]
+    pub send_events: bool,
+    /// Whether changes are multicast to the network
+    #[serde(deserialize_with = "deserialize_yes_no", default)]
+    pub multicast: bool,
+}
+
+/// Bounds for a numeric state variable
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct AllowedValueRange {
+    pub minimum: String,
+    pub maximum: String,
+    pub step: Option<String>,
+}
+
+/// The type of a state variable
+#[derive(Debug, PartialEq, Clone)]
+pub enum DataType {
+    UI1,
+    UI2,
+    UI4,
+    UI8,
+    I1,
+    I2,
+    I4,
+    I8,
+    Int,
+    R4,
+    R8,
+    Number,
+    Fixed14_4,
+    Float,
+    Char,
+    String,
+    Date,
+    DateTime,
+    DateTimeTZ,
+    Time,
+    TimeTZ,
+    Boolean,
+    BinBase64,
+    BinHex,
+    URI,
+    UUID,
+    /// Vendor-defined types
+    Other(String),
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::UI1 => "ui1",
+            Self::UI2 => "ui2",
+            Self::UI4 => "ui4",
+            Self::UI8 => "ui8",
+            Self::I1 => "i1",
+            Self::I2 => "i2",
+            Self::I4 => "i4",
+            Self::I8 => "i8",
+            Self::Int => "int",
+            Self::R4 => "r4",
+            Self::R8 => "r8",
+            Self::Number => "number",
+            Self::Fixed14_4 => "fixed.14.4",
+            Self::Float => "float",
+            Self::Char => "char",
+            Self::String => "string",
+            Self::Date => "date",
+            Self::DateTime => "dateTime",
+            Self::DateTimeTZ => "dateTime.tz",
+            Self::Time => "time",
+            Self::TimeTZ => "time.tz",
+            Self::Boolean => "boolean",
+            Self::BinBase64 => "bin.base64",
+            Self::BinHex => "bin.hex",
+            Self::URI => "uri",
+            Self::UUID => "uuid",
+            Self::Other(name) => name,
+        })
+    }
+}
+
+impl FromStr for DataType {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "ui1" => Self::UI1,
+            "ui2" => Self::UI2,
+            "ui4" => Self::UI4,
+            "ui8" => Self::UI8,
+            "i1" => Self::I1,
+            "i2" => Self::I2,
+            "i4" => Self::I4,
+            "i8" => Self::I8,
+            "int" => Self::Int,
+            "r4" => Self::R4,
+            "r8" => Self::R8,
+            "number" => Self::Number,
+            "fixed.14.4" => Self::Fixed14_4,
+            "float" => Self::Float,
+            "char" => Self::Char,
+            "string" => Self::String,
+            "date" => Self::Date,
+            "dateTime" => Self::DateTime,
+            "dateTime.tz" => Self::DateTimeTZ,
+            "time" => Self::Time,
+            "time.tz" => Self::TimeTZ,
+            "boolean" => Self::Boolean,
+            "bin.base64" => Self::BinBase64,
+            "bin.hex" => Self::BinHex,
+            "uri" => Self::URI,
+            "uuid" => Self::UUID,
+            other => Self::Other(other.to_owned()),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ActionOuter {
+    #[serde(default)]
+    action: Vec<Action>,
+}
+
+/// Flatten the `action` list down
+fn deserialize_actions<'de, D>(d: D) -> Result<Vec<Action>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    ActionOuter::deserialize(d).map(|a| a.action)
+}
+
+#[derive(Deserialize)]
+struct ArgumentOuter {
+    #[serde(default)]
+    argument: Vec<Argument>,
+}
+
+/// Flatten the `argument` list down
+fn deserialize_arguments<'de, D>(d: D) -> Result<Vec<Argument>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    ArgumentOuter::deserialize(d).map(|a| a.argument)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StateVariableOuter {
+    state_variable: Vec<StateVariable>,
+}
+
+/// Flatten the `stateVariable` list down
+fn deserialize_state_variables<'de, D>(d: D) -> Result<Vec<StateVariable>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    StateVariableOuter::deserialize(d).map(|v| v.state_variable)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AllowedValueOuter {
+    allowed_value: Vec<String>,
+}
+
+/// Flatten the `allowedValue` list down
+fn deserialize_allowed_values<'de, D>(d: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    AllowedValueOuter::deserialize(d).map(|v| v.allowed_value)
+}
+
+/// An empty element like `<retval/>` is true if present
+fn deserialize_flag<'de, D>(d: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    serde::de::IgnoredAny::deserialize(d).map(|_| true)
+}
+
+fn deserialize_yes_no<'de, D>(d: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match String::deserialize(d)?.as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        v => Err(serde::de::Error::custom(format!(
+            "expected yes or no, got {}",
+            v
+        ))),
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
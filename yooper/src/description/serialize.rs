@@ -0,0 +1,150 @@
+//! Writing description documents, for hosting devices of your own
+use std::io::Write;
+
+use xml::common::XmlVersion;
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
+
+use super::{Description, Device, Icon, Service, SpecVersion, DEVICE_NAMESPACE};
+use crate::Error;
+
+impl Description {
+    /// Write this document as XML, such that [from_slice](#method.from_slice) reads it back.
+    /// URLs are written as they are, so make them absolute
+    /// or relative to where the document will be served.
+    pub fn write_xml<W: Write>(&self, sink: W) -> Result<(), Error> {
+        let mut w = EmitterConfig::new()
+            .perform_indent(true)
+            .normalize_empty_elements(false)
+            .create_writer(sink);
+
+        w.write(XmlEvent::StartDocument {
+            version: XmlVersion::Version10,
+            encoding: Some("utf-8"),
+            standalone: None,
+        })?;
+        let mut root = XmlEvent::start_element("root").default_ns(DEVICE_NAMESPACE);
+        if let Some(config_id) = &self.config_id {
+            root = root.attr("configId", config_id);
+        }
+        w.write(root)?;
+
+        self.spec_version.write_xml(&mut w)?;
+        optional(&mut w, "URLBase", &self.url_base)?;
+        self.device.write_xml(&mut w)?;
+
+        w.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+
+    /// Write this document to a string. See [write_xml](#method.write_xml)
+    pub fn to_xml(&self) -> Result<String, Error> {
+        let mut buf = Vec::new();
+        self.write_xml(&mut buf)?;
+        Ok(String::from_utf8(buf).map_err(|e| e.utf8_error())?)
+    }
+}
+
+impl SpecVersion {
+    fn write_xml<W: Write>(&self, w: &mut EventWriter<W>) -> Result<(), Error> {
+        w.write(XmlEvent::start_element("specVersion"))?;
+        element(w, "major", &self.major.to_string())?;
+        element(w, "minor", &self.minor.to_string())?;
+        w.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+impl Device {
+    /// Elements are written in the order the UPnP Device Architecture lists them, then extensions
+    fn write_xml<W: Write>(&self, w: &mut EventWriter<W>) -> Result<(), Error> {
+        w.write(XmlEvent::start_element("device"))?;
+        element(w, "deviceType", &self.device_type.to_string())?;
+        element(w, "friendlyName", &self.friendly_name)?;
+        element(w, "manufacturer", &self.manufacturer)?;
+        optional(w, "manufacturerURL", &self.manufacturer_url)?;
+        optional(w, "modelDescription", &self.model_description)?;
+        optional(w, "modelName", &self.model_name)?;
+        optional(w, "modelNumber", &self.model_number)?;
+        optional(w, "modelURL", &self.model_url)?;
+        optional(w, "serialNumber", &self.serial_number)?;
+        element(w, "UDN", &self.unique_device_name.to_string())?;
+        optional(w, "UPC", &self.upc)?;
+
+        list(w, "iconList", &self.icons, Icon::write_xml)?;
+        list(w, "serviceList", &self.services, Service::write_xml)?;
+        list(w, "deviceList", &self.devices, Device::write_xml)?;
+
+        optional(w, "presentationURL", &self.presentation_url)?;
+        for extension in &self.extensions {
+            extension.write_xml(w)?;
+        }
+        w.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+impl Icon {
+    fn write_xml<W: Write>(&self, w: &mut EventWriter<W>) -> Result<(), Error> {
+        w.write(XmlEvent::start_element("icon"))?;
+        element(w, "mimetype", &self.mimetype)?;
+        element(w, "width", &self.width.to_string())?;
+        element(w, "height", &self.height.to_string())?;
+        element(w, "depth", &self.depth.to_string())?;
+        element(w, "url", &self.url)?;
+        w.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+impl Service {
+    fn write_xml<W: Write>(&self, w: &mut EventWriter<W>) -> Result<(), Error> {
+        w.write(XmlEvent::start_element("service"))?;
+        element(w, "serviceType", &self.service_type.to_string())?;
+        element(w, "serviceId", &self.service_id.to_string())?;
+        element(w, "SCPDURL", &self.scpd_url)?;
+        element(w, "controlURL", &self.control_url)?;
+        element(w, "eventSubURL", &self.event_sub_url)?;
+        for extension in &self.extensions {
+            extension.write_xml(w)?;
+        }
+        w.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// `<name>value</name>`
+fn element<W: Write>(w: &mut EventWriter<W>, name: &str, value: &str) -> Result<(), Error> {
+    w.write(XmlEvent::start_element(name))?;
+    w.write(XmlEvent::characters(value))?;
+    w.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+/// Like [element], but left out entirely if there's no value
+fn optional<W: Write>(
+    w: &mut EventWriter<W>,
+    name: &str,
+    value: &Option<String>,
+) -> Result<(), Error> {
+    match value {
+        Some(value) => element(w, name, value),
+        None => Ok(()),
+    }
+}
+
+/// Wrap `items` in a `name` element, leaving it out if there are none
+fn list<W, T, F>(w: &mut EventWriter<W>, name: &str, items: &[T], write: F) -> Result<(), Error>
+where
+    W: Write,
+    F: Fn(&T, &mut EventWriter<W>) -> Result<(), Error>,
+{
+    if items.is_empty() {
+        return Ok(());
+    }
+    w.write(XmlEvent::start_element(name))?;
+    for item in items {
+        write(item, w)?;
+    }
+    w.write(XmlEvent::end_element())?;
+    Ok(())
+}
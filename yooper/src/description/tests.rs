@@ -1,4 +1,6 @@
 use super::*;
+use crate::ssdp::message::Message;
+use crate::ssdp::packet::Headers;
 
 // Harvested from my wireless router
 const IGD_EXAMPLE: &str = include_str!("testdata/igd.xml");
@@ -37,6 +39,7 @@ fn test_deserialize_with_example() {
     let expected = Description {
         config_id: None,
         spec_version: SpecVersion { major: 1, minor: 0 },
+        url_base: Some("http://192.168.7.1:1900/".into()),
         device: Device {
             device_type: DeviceType {
                 vendor_domain: None,
@@ -55,6 +58,7 @@ fn test_deserialize_with_example() {
             serial_number: None,
             unique_device_name: UniqueDeviceName { uuid: "".into() },
             upc: None,
+            icons: vec![],
             services: vec![Service {
                 service_type: ServiceType {
                     vendor_domain: None,
@@ -68,6 +72,7 @@ fn test_deserialize_with_example() {
                 scpd_url: "/l3f.xml".into(),
                 control_url: "/l3f".into(),
                 event_sub_url: "/l3f/events".into(),
+                extensions: vec![],
             }],
 
             devices: vec![Device {
@@ -88,6 +93,7 @@ fn test_deserialize_with_example() {
                 serial_number: None,
                 unique_device_name: UniqueDeviceName { uuid: "".into() },
                 upc: None,
+                icons: vec![],
                 services: vec![Service {
                     service_type: ServiceType {
                         vendor_domain: None,
@@ -101,6 +107,7 @@ fn test_deserialize_with_example() {
                     scpd_url: "/ifc.xml".into(),
                     control_url: "/ifc".into(),
                     event_sub_url: "/ifc/events".into(),
+                    extensions: vec![],
                 }],
 
                 devices: vec![Device {
@@ -121,6 +128,7 @@ fn test_deserialize_with_example() {
                     serial_number: None,
                     unique_device_name: UniqueDeviceName { uuid: "".into() },
                     upc: None,
+                    icons: vec![],
                     services: vec![Service {
                         service_type: ServiceType {
                             vendor_domain: None,
@@ -134,15 +142,1037 @@ fn test_deserialize_with_example() {
                         scpd_url: "/ipc.xml".into(),
                         control_url: "/ipc".into(),
                         event_sub_url: "/ipc/events".into(),
+                        extensions: vec![],
                     }],
                     devices: vec![],
                     presentation_url: None,
+                    extensions: vec![],
                 }],
                 presentation_url: None,
+                extensions: vec![],
             }],
             presentation_url: Some("".into()),
+            extensions: vec![],
         },
     };
 
     assert_eq!(expected, serde_xml_rs::from_str(IGD_EXAMPLE).unwrap());
 }
+
+const MEDIA_SERVER_EXAMPLE: &str = include_str!("testdata/mediaserver.xml");
+
+#[test]
+fn test_icons() {
+    let mut document: Description = serde_xml_rs::from_str(MEDIA_SERVER_EXAMPLE).unwrap();
+    let base = Url::parse("http://192.168.7.238:54216/desc/RootDevice.xml").unwrap();
+    document.resolve_urls(&base).unwrap();
+    let device = document.device;
+
+    assert_eq!(
+        device.icons[0],
+        Icon {
+            mimetype: "image/png".into(),
+            width: 48,
+            height: 48,
+            depth: 24,
+            url: "http://192.168.7.238:54216/icons/sm.png".into(),
+        }
+    );
+    assert_eq!(
+        device.icons[2].url,
+        "http://192.168.7.238:54216/desc/icons/lrg.jpg"
+    );
+
+    assert_eq!(device.icon_for(32, None), Some(&device.icons[0]));
+    assert_eq!(
+        device.icon_for(64, Some("image/png")),
+        Some(&device.icons[1])
+    );
+    assert_eq!(
+        device.icon_for(64, Some("image/jpeg")),
+        Some(&device.icons[2])
+    );
+    assert_eq!(
+        device.icon_for(256, Some("image/png")),
+        Some(&device.icons[1])
+    );
+    assert_eq!(device.icon_for(64, Some("image/gif")), None);
+
+    // Big enough to overflow a u32 area
+    let mut device = device;
+    device.icons[1].width = 70000;
+    device.icons[1].height = 70000;
+    assert_eq!(
+        device.icon_for(256, Some("image/png")),
+        Some(&device.icons[1])
+    );
+}
+
+#[test]
+fn test_resolve_urls() {
+    let mut document: Description = serde_xml_rs::from_str(MEDIA_SERVER_EXAMPLE).unwrap();
+    let base = Url::parse("http://192.168.7.238:54216/desc/RootDevice.xml").unwrap();
+    document.resolve_urls(&base).unwrap();
+    let service = &document.device.services[0];
+
+    assert_eq!(
+        service.scpd_url,
+        "http://192.168.7.238:54216/ContentDirectory/scpd.xml"
+    );
+    assert_eq!(
+        service.control_url,
+        "http://192.168.7.238:54216/ContentDirectory/control"
+    );
+    assert_eq!(document.device.presentation_url, None);
+}
+
+#[test]
+fn test_resolve_urls_with_url_base() {
+    let mut document: Description = serde_xml_rs::from_str(IGD_EXAMPLE).unwrap();
+    // URLBase wins over where the document came from
+    let base = Url::parse("http://10.0.0.1/igd.xml").unwrap();
+    document.resolve_urls(&base).unwrap();
+    let device = &document.device;
+
+    assert_eq!(
+        device.services[0].scpd_url,
+        "http://192.168.7.1:1900/l3f.xml"
+    );
+    assert_eq!(
+        device.devices[0].devices[0].services[0].event_sub_url,
+        "http://192.168.7.1:1900/ipc/events"
+    );
+    assert_eq!(device.presentation_url, None);
+}
+
+#[test]
+fn test_resolve_bad_url() {
+    let mut document: Description = serde_xml_rs::from_str(MEDIA_SERVER_EXAMPLE).unwrap();
+    document.device.services[0].control_url = "http://[::1".into();
+    let base = Url::parse("http://192.168.7.238:54216/RootDevice.xml").unwrap();
+
+    match document.resolve_urls(&base).unwrap_err() {
+        Error::MalformedField("controlURL", v) if v == "http://[::1" => (),
+        e => panic!("Didn't get the error we assumed! {:?}", e),
+    };
+}
+
+const WAN_IP_CONNECTION_SCPD: &str = include_str!("testdata/wanipconnection.xml");
+
+#[test]
+fn test_deserialize_scpd() {
+    let scpd: ServiceDescription = serde_xml_rs::from_str(WAN_IP_CONNECTION_SCPD).unwrap();
+
+    assert_eq!(scpd.spec_version, SpecVersion { major: 1, minor: 0 });
+    assert_eq!(scpd.action_list.len(), 4);
+
+    let action = scpd.action("GetExternalIPAddress").unwrap();
+    assert_eq!(
+        action.argument_list,
+        vec![Argument {
+            name: "NewExternalIPAddress".into(),
+            direction: Direction::Out,
+            retval: true,
+            related_state_variable: "ExternalIPAddress".into(),
+        }]
+    );
+
+    let action = scpd.action("AddPortMapping").unwrap();
+    assert_eq!(action.in_arguments().count(), 8);
+    assert_eq!(action.out_arguments().count(), 0);
+    assert!(!action.argument_list[0].retval);
+
+    assert_eq!(
+        scpd.state_variable("ConnectionStatus").unwrap(),
+        &StateVariable {
+            name: "ConnectionStatus".into(),
+            data_type: DataType::String,
+            default_value: Some("Unconfigured".into()),
+            allowed_values: vec![
+                "Unconfigured".into(),
+                "Connecting".into(),
+                "Connected".into(),
+                "PendingDisconnect".into(),
+                "Disconnecting".into(),
+                "Disconnected".into(),
+            ],
+            allowed_range: None,
+            send_events: true,
+            multicast: false,
+        }
+    );
+
+    let lease = scpd.state_variable("PortMappingLeaseDuration").unwrap();
+    assert_eq!(lease.data_type, DataType::UI4);
+    assert!(!lease.send_events);
+    assert_eq!(
+        lease.allowed_range,
+        Some(AllowedValueRange {
+            minimum: "0".into(),
+            maximum: "604800".into(),
+            step: Some("1".into()),
+        })
+    );
+}
+
+#[test]
+fn test_parse_from_str_and_slice() {
+    let from_str: Description = IGD_EXAMPLE.parse().unwrap();
+    let from_slice = Description::from_slice(IGD_EXAMPLE.as_bytes()).unwrap();
+    assert_eq!(from_str, from_slice);
+    // Nothing is resolved until asked
+    assert_eq!(from_str.device.services[0].scpd_url, "/l3f.xml");
+
+    let scpd: ServiceDescription = WAN_IP_CONNECTION_SCPD.parse().unwrap();
+    assert_eq!(
+        scpd,
+        ServiceDescription::from_slice(WAN_IP_CONNECTION_SCPD.as_bytes()).unwrap()
+    );
+}
+
+#[test]
+fn test_serialize_round_trip() {
+    for example in &[IGD_EXAMPLE, MEDIA_SERVER_EXAMPLE] {
+        let mut document: Description = example.parse().unwrap();
+        document.config_id = Some("42".into());
+
+        let xml = document.to_xml().unwrap();
+        assert!(xml.contains(r#"<root xmlns="urn:schemas-upnp-org:device-1-0" configId="42">"#));
+        assert_eq!(document, xml.parse().unwrap());
+    }
+}
+
+#[test]
+fn test_serialize_lists() {
+    let document: Description = MEDIA_SERVER_EXAMPLE.parse().unwrap();
+    let xml = document.to_xml().unwrap();
+
+    assert!(xml.contains("<iconList>\n      <icon>\n        <mimetype>image/png</mimetype>"));
+    assert!(
+        xml.contains("<serviceType>urn:schemas-upnp-org:service:ContentDirectory:1</serviceType>")
+    );
+    assert!(xml.contains("<UDN>uuid:"));
+    // No embedded devices, so no empty list either
+    assert!(!xml.contains("deviceList"));
+}
+
+const RENDERER_EXAMPLE: &str = include_str!("testdata/renderer.xml");
+
+#[test]
+fn test_extensions() {
+    let document: Description = RENDERER_EXAMPLE.parse().unwrap();
+    let device = &document.device;
+
+    let names: Vec<&str> = device.extensions.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, vec!["roomName", "displayName"]);
+    assert_eq!(
+        device.extensions[0],
+        Extension {
+            name: "roomName".into(),
+            namespace: Some("urn:schemas-upnp-org:device-1-0".into()),
+            prefix: None,
+            attributes: vec![],
+            text: "Kitchen".into(),
+            children: vec![],
+        }
+    );
+    assert!(device.services[0].extensions.is_empty());
+
+    let renderer = &device.devices[0];
+    assert_eq!(renderer.extensions.len(), 2);
+    assert!(renderer.extensions[0].is("urn:schemas-dlna-org:device-1-0", "X_DLNADOC"));
+    assert_eq!(renderer.extensions[0].prefix.as_deref(), Some("dlna"));
+    assert_eq!(renderer.extensions[0].text, "DMR-1.50");
+    assert_eq!(renderer.extensions[1].text, "");
+
+    let capabilities = &renderer.services[0].extensions[0];
+    assert!(capabilities.is("urn:schemas-microsoft-com:WMPNSS-1-0", "X_MS_Capabilities"));
+    assert_eq!(capabilities.attribute("version"), Some("2"));
+    assert_eq!(capabilities.children[0].name, "volume");
+    assert_eq!(capabilities.children[0].text, "true");
+}
+
+#[test]
+fn test_deeply_nested_extensions() {
+    let depth = 100;
+    let nested = format!("{}{}", "<x>".repeat(depth), "</x>".repeat(depth));
+    let document = RENDERER_EXAMPLE.replace("<roomName>Kitchen</roomName>", &nested);
+    match document.parse::<Description>() {
+        Err(Error::ParseFailure(_)) => (),
+        other => panic!("expected a parse failure, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_serialize_extensions() {
+    let document: Description = RENDERER_EXAMPLE.parse().unwrap();
+    let xml = document.to_xml().unwrap();
+
+    assert!(xml.contains("<roomName>Kitchen</roomName>"));
+    assert!(xml.contains(
+        r#"<dlna:X_DLNADOC xmlns:dlna="urn:schemas-dlna-org:device-1-0">DMR-1.50</dlna:X_DLNADOC>"#
+    ));
+    assert_eq!(document, xml.parse().unwrap());
+}
+
+#[test]
+fn test_walk_devices() {
+    let document: Description = IGD_EXAMPLE.parse().unwrap();
+
+    let walked: Vec<(Vec<String>, String)> = document
+        .device
+        .walk_devices()
+        .map(|(path, d)| {
+            (
+                path.iter()
+                    .map(|p| p.device_type.device_type.clone())
+                    .collect(),
+                d.device_type.device_type.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        walked,
+        vec![
+            (vec![], "InternetGatewayDevice".into()),
+            (vec!["InternetGatewayDevice".into()], "WANDevice".into()),
+            (
+                vec!["InternetGatewayDevice".into(), "WANDevice".into()],
+                "WANConnectionDevice".into()
+            ),
+        ]
+    );
+
+    // A service's path is its device's path, followed by the device itself
+    let devices: Vec<_> = document.device.walk_devices().collect();
+    let services: Vec<_> = document.device.walk_services().collect();
+    assert_eq!(services.len(), 3);
+    for (path, service) in &services {
+        let (device_path, device) = devices
+            .iter()
+            .find(|(_, d)| d.services.iter().any(|s| std::ptr::eq(s, *service)))
+            .unwrap();
+        assert_eq!(path.len(), device_path.len() + 1);
+        assert!(std::ptr::eq(*path.last().unwrap(), *device));
+    }
+
+    let (path, service) = services.last().unwrap();
+    assert_eq!(service.service_id.service_id, "WANIPConnection");
+    assert_eq!(
+        path.last().unwrap().device_type.device_type,
+        "WANConnectionDevice"
+    );
+}
+
+#[test]
+fn test_find() {
+    let document: Description = IGD_EXAMPLE.parse().unwrap();
+    let device = &document.device;
+
+    let wanted = "urn:schemas-upnp-org:service:WANIPConnection:1"
+        .parse()
+        .unwrap();
+    let service = device.find_service(&wanted).unwrap();
+    assert_eq!(service.scpd_url, "/ipc.xml");
+
+    // A v1 service can't stand in for v2
+    let wanted = "urn:schemas-upnp-org:service:WANIPConnection:2"
+        .parse()
+        .unwrap();
+    assert_eq!(device.find_service(&wanted), None);
+
+    let id = "urn:upnp-org:serviceId:WANCommonInterfaceConfig"
+        .parse()
+        .unwrap();
+    assert_eq!(device.find_service_by_id(&id).unwrap().scpd_url, "/ifc.xml");
+
+    let wanted = "urn:schemas-upnp-org:device:WANConnectionDevice:1"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        device.find_device(&wanted).unwrap().services[0].service_id,
+        "urn:upnp-org:serviceId:WANIPConnection".parse().unwrap()
+    );
+
+    let document: Description = RENDERER_EXAMPLE.parse().unwrap();
+    let udn = "uuid:RINCON_000E58000000001400_MR".parse().unwrap();
+    assert_eq!(
+        document
+            .device
+            .find_device_by_udn(&udn)
+            .unwrap()
+            .friendly_name,
+        "Kitchen - Sonos Play:1 Media Renderer"
+    );
+}
+
+#[test]
+fn test_version_compatibility() {
+    let v2: ServiceType = "urn:schemas-upnp-org:service:WANIPConnection:2"
+        .parse()
+        .unwrap();
+    let v1: ServiceType = "urn:schemas-upnp-org:service:WANIPConnection:1"
+        .parse()
+        .unwrap();
+    assert!(v2.is_compatible_with(&v1));
+    assert!(!v1.is_compatible_with(&v2));
+
+    let vendor: ServiceType = "urn:example-com:service:WANIPConnection:2".parse().unwrap();
+    assert!(!vendor.is_compatible_with(&v1));
+
+    let odd: DeviceType = "urn:example-com:device:Thing:1.0a".parse().unwrap();
+    assert!(odd.is_compatible_with(&"urn:example-com:device:Thing:1.0a".parse().unwrap()));
+    assert!(!odd.is_compatible_with(&"urn:example-com:device:Thing:1".parse().unwrap()));
+}
+
+fn testdata(name: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/description/testdata")
+        .join(name)
+}
+
+#[tokio::test]
+async fn test_describe_file() {
+    let path = testdata("mediaserver.xml");
+    let location = Url::from_file_path(&path).unwrap();
+    let described = describe_file(&path).await.unwrap();
+    assert_eq!(described.location, location);
+    assert_eq!(described.server, None);
+    let device = described.description.device;
+
+    assert_eq!(device.friendly_name, "Living Room NAS");
+    assert_eq!(
+        device.services[0].scpd_url,
+        location
+            .join("/ContentDirectory/scpd.xml")
+            .unwrap()
+            .as_str()
+    );
+}
+
+#[tokio::test]
+async fn test_describe_file_too_large() {
+    match describe_file("/dev/zero").await {
+        Err(Error::TooLarge(_)) => (),
+        other => panic!("expected TooLarge, got {:?}", other.map(|_| ())),
+    }
+}
+
+/// Serves canned documents, counting requests
+#[derive(Default)]
+struct FakeFetcher {
+    documents: Vec<(&'static str, &'static str)>,
+    /// Requests for the first URL are served from the second
+    redirect: Option<(&'static str, &'static str)>,
+    headers: Headers,
+    /// Sent as an ETag, and answered with 304 Not Modified when asked for again
+    etag: Option<&'static str>,
+    requests: std::sync::atomic::AtomicUsize,
+}
+
+impl FakeFetcher {
+    fn requests(&self) -> usize {
+        self.requests.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+impl Fetch for FakeFetcher {
+    fn fetch<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+    ) -> futures::future::BoxFuture<'a, Result<Response, Error>> {
+        use futures::future::FutureExt;
+
+        self.requests
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let url = match self.redirect {
+            Some((from, to)) if from == url.as_str() => Url::parse(to).unwrap(),
+            _ => url.clone(),
+        };
+        let mut response_headers = self.headers.clone();
+        if let Some(etag) = self.etag {
+            response_headers.insert("ETag", etag);
+        }
+        let not_modified = self
+            .etag
+            .is_some_and(|etag| headers.get("if-none-match").is_some_and(|v| v == etag));
+
+        let found = self
+            .documents
+            .iter()
+            .find(|(u, _)| *u == url.as_str())
+            .map(|(_, body)| Response {
+                url: url.clone(),
+                status: if not_modified { 304 } else { 200 },
+                headers: response_headers,
+                body: if not_modified {
+                    Bytes::new()
+                } else {
+                    Bytes::from_static(body.as_bytes())
+                },
+            })
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        async move { found }.boxed()
+    }
+}
+
+#[tokio::test]
+async fn test_describe_with_fetcher() {
+    let fetcher = FakeFetcher {
+        documents: vec![
+            ("http://192.168.7.1:1900/igd.xml", IGD_EXAMPLE),
+            ("http://192.168.7.1:1900/ipc.xml", WAN_IP_CONNECTION_SCPD),
+        ],
+        ..Default::default()
+    };
+
+    let described = describe_with(&fetcher, "http://192.168.7.1:1900/igd.xml")
+        .await
+        .unwrap();
+    let service = &described.description.device.devices[0].devices[0].services[0];
+    assert_eq!(service.scpd_url, "http://192.168.7.1:1900/ipc.xml");
+
+    let scpd = describe_service_with(&fetcher, service).await.unwrap();
+    assert!(scpd.action("AddPortMapping").is_some());
+    assert_eq!(fetcher.requests(), 2);
+}
+
+#[tokio::test]
+async fn test_describe_refuses_local_files() {
+    let fetcher = FakeFetcher::default();
+    match describe_with(&fetcher, "file:///etc/passwd").await {
+        Err(Error::UnsupportedScheme(url)) => assert_eq!(url, "file:///etc/passwd"),
+        other => panic!("expected an unsupported scheme, got {:?}", other),
+    }
+
+    // An absolute SCPDURL replaces the device's base
+    let mut description = Description::from_str(IGD_EXAMPLE).unwrap();
+    description.device.devices[0].devices[0].services[0].scpd_url = "file:///dev/zero".into();
+    description
+        .resolve_urls(&Url::parse("http://192.168.7.1:1900/igd.xml").unwrap())
+        .unwrap();
+    let service = &description.device.devices[0].devices[0].services[0];
+    assert!(matches!(
+        describe_service_with(&fetcher, service).await,
+        Err(Error::UnsupportedScheme(_))
+    ));
+    assert_eq!(fetcher.requests(), 0);
+}
+
+#[tokio::test]
+async fn test_describe_metadata() {
+    let fetcher = FakeFetcher {
+        documents: vec![(
+            "http://192.168.7.238:54216/desc/RootDevice.xml",
+            MEDIA_SERVER_EXAMPLE,
+        )],
+        redirect: Some((
+            "http://192.168.7.238:54216/",
+            "http://192.168.7.238:54216/desc/RootDevice.xml",
+        )),
+        headers: vec![
+            ("Server", "Linux/5.4 UPnP/1.1 MediaServer/3.0"),
+            ("Content-Language", "en-CA"),
+            ("CONFIGID.UPNP.ORG", "7"),
+        ]
+        .into_iter()
+        .collect(),
+        ..Default::default()
+    };
+
+    let mut described = describe_with(&fetcher, "http://192.168.7.238:54216/")
+        .await
+        .unwrap();
+    assert_eq!(
+        described.location.as_str(),
+        "http://192.168.7.238:54216/desc/RootDevice.xml"
+    );
+    // Relative URLs resolve against where we ended up
+    assert_eq!(
+        described.description.device.icons[2].url,
+        "http://192.168.7.238:54216/desc/icons/lrg.jpg"
+    );
+    assert_eq!(
+        described.server.as_deref(),
+        Some("Linux/5.4 UPnP/1.1 MediaServer/3.0")
+    );
+    assert_eq!(described.content_language.as_deref(), Some("en-CA"));
+    assert_eq!(described.config_id, Some(7));
+
+    assert!(described.config_id_matches(Some(7)));
+    assert!(described.config_id_matches(None));
+    assert!(!described.config_id_matches(Some(8)));
+
+    described.config_id = None;
+    described.description.config_id = Some("8".into());
+    assert!(!described.config_id_matches(Some(7)));
+}
+
+#[tokio::test]
+async fn test_describe_bad_metadata() {
+    let mut fetcher = FakeFetcher {
+        documents: vec![("http://192.168.7.1:1900/igd.xml", IGD_EXAMPLE)],
+        headers: vec![("CONFIGID.UPNP.ORG", "lots")].into_iter().collect(),
+        ..Default::default()
+    };
+    fetcher.headers.insert(
+        "Server",
+        crate::ssdp::packet::HeaderValue::from_bytes(Bytes::from_static(b"Router \xff")),
+    );
+
+    let described = describe_with(&fetcher, "http://192.168.7.1:1900/igd.xml")
+        .await
+        .unwrap();
+    assert_eq!(described.config_id, None);
+    assert_eq!(described.server, None);
+}
+
+const MEDIA_SERVER_LOCATION: &str = "http://192.168.7.238:54216/RootDevice.xml";
+
+fn media_server() -> FakeFetcher {
+    FakeFetcher {
+        documents: vec![(MEDIA_SERVER_LOCATION, MEDIA_SERVER_EXAMPLE)],
+        ..Default::default()
+    }
+}
+
+fn media_server_alive(boot_id: &'static str, config_id: &'static str) -> Message {
+    use crate::ssdp::packet::{FromPacket, Packet, PacketType};
+
+    Message::from_packet(&Packet::new_from_literal(
+        PacketType::Notify,
+        vec![
+            ("host", "239.255.255.250:1900"),
+            ("cache-control", "max-age=3600"),
+            ("location", MEDIA_SERVER_LOCATION),
+            ("nt", "upnp:rootdevice"),
+            ("nts", "ssdp:alive"),
+            ("server", "Linux/5.4 UPnP/1.1 MediaServer/3.0"),
+            (
+                "usn",
+                "uuid:07853410-ccef-9e3c-de6a-410b371182eb::upnp:rootdevice",
+            ),
+            ("bootid.upnp.org", boot_id),
+            ("configid.upnp.org", config_id),
+        ],
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_cache_hit() {
+    let mut cache = Cache::with_fetcher(media_server());
+
+    let first = cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+    let second = cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+    assert!(std::sync::Arc::ptr_eq(&first, &second));
+
+    let udn = "uuid:07853410-ccef-9e3c-de6a-410b371182eb".parse().unwrap();
+    assert!(std::sync::Arc::ptr_eq(&cache.get(&udn).unwrap(), &first));
+}
+
+#[tokio::test]
+async fn test_cache_invalidated_by_ssdp() {
+    let mut cache = Cache::with_fetcher(media_server());
+    let first = cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+
+    // The first IDs we hear of are just remembered
+    cache.notice(&media_server_alive("1", "7"));
+    cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+    cache.notice(&media_server_alive("1", "7"));
+    cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+
+    cache.notice(&media_server_alive("1", "8"));
+    let second = cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+    assert!(!std::sync::Arc::ptr_eq(&first, &second));
+
+    cache.notice(&media_server_alive("2", "8"));
+    cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+
+    assert_eq!(cache.fetcher().requests(), 3);
+}
+
+#[tokio::test]
+async fn test_cache_revalidates_etag() {
+    let mut cache = Cache::with_fetcher(FakeFetcher {
+        etag: Some("\"v1\""),
+        ..media_server()
+    });
+    let first = cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+
+    cache.invalidate(MEDIA_SERVER_LOCATION);
+    let second = cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+
+    assert_eq!(cache.fetcher().requests(), 2);
+    assert!(std::sync::Arc::ptr_eq(&first, &second));
+}
+
+#[tokio::test]
+async fn test_cache_byebye() {
+    use crate::ssdp::packet::{FromPacket, Packet, PacketType};
+
+    let mut cache = Cache::with_fetcher(media_server());
+    cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+
+    let byebye = Message::from_packet(&Packet::new_from_literal(
+        PacketType::Notify,
+        vec![
+            ("host", "239.255.255.250:1900"),
+            ("nt", "upnp:rootdevice"),
+            ("nts", "ssdp:byebye"),
+            (
+                "usn",
+                "uuid:07853410-ccef-9e3c-de6a-410b371182eb::upnp:rootdevice",
+            ),
+        ],
+    ))
+    .unwrap();
+    cache.notice(&byebye);
+
+    let udn = "uuid:07853410-ccef-9e3c-de6a-410b371182eb".parse().unwrap();
+    assert!(cache.get(&udn).is_none());
+}
+
+#[tokio::test]
+async fn test_cache_persists() {
+    let path = std::env::temp_dir().join(format!("yooper-cache-{}.xml", std::process::id()));
+
+    let mut cache = Cache::with_fetcher(FakeFetcher {
+        etag: Some("\"v1\""),
+        ..media_server()
+    });
+    let first = cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+    cache.notice(&media_server_alive("3", "7"));
+    cache.save(&path).unwrap();
+
+    let mut loaded = Cache::with_fetcher(FakeFetcher {
+        etag: Some("\"v1\""),
+        ..media_server()
+    });
+    loaded.load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let udn = "uuid:07853410-ccef-9e3c-de6a-410b371182eb".parse().unwrap();
+    assert_eq!(loaded.get(&udn).unwrap().description, first.description);
+
+    // Loaded entries are checked with the device, which says they haven't changed
+    let second = loaded.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+    assert_eq!(second.description, first.description);
+    assert_eq!(second.location, first.location);
+    assert_eq!(loaded.fetcher().requests(), 1);
+
+    // Missing files are fine
+    loaded.load(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_cache_rejects_bad_status() {
+    let path = std::env::temp_dir().join(format!("yooper-status-{}.xml", std::process::id()));
+
+    let mut cache = Cache::with_fetcher(media_server());
+    cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+    cache.save(&path).unwrap();
+
+    // 65736 would wrap around to 200 if it were truncated
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("status=\"200\""));
+    std::fs::write(&path, saved.replace("status=\"200\"", "status=\"65736\"")).unwrap();
+
+    let mut loaded = Cache::with_fetcher(media_server());
+    let result = loaded.load(&path);
+    std::fs::remove_file(&path).unwrap();
+    match result.unwrap_err() {
+        Error::MalformedField("status", v) if v == "65736" => (),
+        e => panic!("Didn't get the error we assumed! {:?}", e),
+    };
+}
+
+#[tokio::test]
+async fn test_cache_skips_binary_entries() {
+    let path = std::env::temp_dir().join(format!("yooper-binary-{}.xml", std::process::id()));
+
+    let mut headers = Headers::new();
+    headers.insert(
+        "Server",
+        crate::ssdp::packet::HeaderValue::from_bytes(Bytes::from_static(b"NAS \xff")),
+    );
+    let mut cache = Cache::with_fetcher(FakeFetcher {
+        headers,
+        ..media_server()
+    });
+    cache.describe(MEDIA_SERVER_LOCATION).await.unwrap();
+    cache.save(&path).unwrap();
+
+    let mut loaded = Cache::with_fetcher(media_server());
+    loaded.load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let udn = "uuid:07853410-ccef-9e3c-de6a-410b371182eb".parse().unwrap();
+    assert!(loaded.get(&udn).is_none());
+}
+
+/// Serves the media server slowly, recording how many requests were in flight at once
+#[derive(Default)]
+struct SlowFetcher {
+    in_flight: std::sync::atomic::AtomicUsize,
+    most_in_flight: std::sync::atomic::AtomicUsize,
+}
+
+impl Fetch for SlowFetcher {
+    fn fetch<'a>(
+        &'a self,
+        url: &'a Url,
+        _headers: &'a Headers,
+    ) -> futures::future::BoxFuture<'a, Result<Response, Error>> {
+        use futures::future::FutureExt;
+        use std::sync::atomic::Ordering::SeqCst;
+
+        async move {
+            let now = self.in_flight.fetch_add(1, SeqCst) + 1;
+            self.most_in_flight.fetch_max(now, SeqCst);
+            tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+            self.in_flight.fetch_sub(1, SeqCst);
+
+            if url.path() == "/missing.xml" {
+                return Err(Error::HTTPStatus(404));
+            }
+            Ok(Response {
+                url: url.clone(),
+                status: 200,
+                headers: Headers::new(),
+                body: Bytes::from_static(MEDIA_SERVER_EXAMPLE.as_bytes()),
+            })
+        }
+        .boxed()
+    }
+}
+
+fn discovered(uuid: &str, location: &str) -> crate::discovery::Device {
+    crate::discovery::Device {
+        server: "Linux/5.4 UPnP/1.1 MediaServer/3.0".into(),
+        address: "192.168.7.238:1900".parse().unwrap(),
+        services: vec![crate::discovery::Service {
+            service_name: format!("uuid:{}::upnp:rootdevice", uuid).parse().unwrap(),
+            target: crate::ssdp::message::SearchTarget::RootDevice,
+        }],
+        location: location.into(),
+    }
+}
+
+#[tokio::test]
+async fn test_describe_all() {
+    let mut devices = vec![
+        // A root device and its embedded device share a description
+        discovered("root", "http://192.168.7.238:54216/RootDevice.xml"),
+        discovered("embedded", "http://192.168.7.238:54216/RootDevice.xml"),
+        discovered("gone", "http://192.168.7.9/missing.xml"),
+        // Locations come from the network, so only http(s) ones are fetched
+        discovered("local", "file:///etc/passwd"),
+        discovered("garbled", "not a url"),
+    ];
+    for i in 0..6 {
+        devices.push(discovered(
+            "other",
+            &format!("http://192.168.7.{}/desc.xml", 10 + i),
+        ));
+    }
+    let fetcher = SlowFetcher::default();
+
+    let described = describe_all(&fetcher, devices, 3).await;
+
+    assert_eq!(described.len(), 10);
+    assert_eq!(described[0].discovered.len(), 2);
+    assert_eq!(
+        described[0]
+            .description
+            .as_ref()
+            .unwrap()
+            .description
+            .device
+            .friendly_name,
+        "Living Room NAS"
+    );
+    match &described[1].description {
+        Err(Error::HTTPStatus(404)) => (),
+        e => panic!("Didn't get the error we assumed! {:?}", e),
+    }
+    match &described[2].description {
+        Err(Error::UnsupportedScheme(url)) => assert_eq!(url, "file:///etc/passwd"),
+        e => panic!("Didn't get the error we assumed! {:?}", e),
+    }
+    match &described[3].description {
+        Err(Error::URLError(_)) => (),
+        e => panic!("Didn't get the error we assumed! {:?}", e),
+    }
+    assert!(described[4..].iter().all(|d| d.description.is_ok()));
+    assert_eq!(
+        fetcher
+            .most_in_flight
+            .load(std::sync::atomic::Ordering::SeqCst),
+        3
+    );
+}
+
+#[test]
+fn test_value_round_trips() {
+    let examples = [
+        (DataType::UI1, "255"),
+        (DataType::I4, "-2147483648"),
+        (DataType::UI8, "18446744073709551615"),
+        (DataType::R8, "3.25"),
+        (DataType::Fixed14_4, "12345678901234.5678"),
+        (DataType::Char, "é"),
+        (DataType::String, " spaced out "),
+        (DataType::Date, "2020-02-29"),
+        (DataType::DateTime, "2020-06-01T13:30:00"),
+        (DataType::DateTimeTZ, "2020-06-01T13:30:00-04:00"),
+        (DataType::DateTimeTZ, "2020-06-01T13:30:00Z"),
+        (DataType::DateTimeTZ, "2020-06-01"),
+        (DataType::Time, "00:00:59"),
+        (DataType::TimeTZ, "23:59:59+05:30"),
+        (DataType::Boolean, "1"),
+        (DataType::BinBase64, "eW9vcGVy"),
+        (DataType::BinHex, "00ff7f"),
+        (DataType::URI, "/ctl/IPConn"),
+        (DataType::UUID, "2fac1234-31f8-11b4-a222-08002b34c003"),
+        (DataType::Other("X_Vendor".into()), "anything"),
+    ];
+    for (data_type, s) in &examples {
+        let value = Value::parse(data_type, s).unwrap();
+        assert_eq!(value.data_type(), *data_type);
+        assert_eq!(value.to_string(), *s);
+    }
+
+    for (s, b) in &[
+        ("true", true),
+        ("yes", true),
+        ("0", false),
+        ("false", false),
+        ("no", false),
+    ] {
+        assert_eq!(
+            Value::parse(&DataType::Boolean, s).unwrap(),
+            Value::Boolean(*b)
+        );
+    }
+    assert_eq!(
+        Value::parse(&DataType::DateTimeTZ, "2020-06-01T13:30:00+01:00").unwrap(),
+        Value::DateTimeTZ(
+            Date {
+                year: 2020,
+                month: 6,
+                day: 1
+            },
+            Some(Time {
+                hour: 13,
+                minute: 30,
+                second: 0
+            }),
+            Some(TimeZone::Offset(60))
+        )
+    );
+}
+
+#[test]
+fn test_invalid_values() {
+    let examples = [
+        (DataType::UI1, "256"),
+        (DataType::UI2, "-1"),
+        (DataType::I1, "1.5"),
+        (DataType::R8, "NaN"),
+        (DataType::Fixed14_4, "1.23456"),
+        (DataType::Fixed14_4, "123456789012345"),
+        (DataType::Char, "ab"),
+        (DataType::Char, ""),
+        (DataType::Date, "2019-02-29"),
+        (DataType::Date, "2020-6-1"),
+        (DataType::DateTime, "2020-06-01T13:30:00Z"),
+        (DataType::DateTimeTZ, "2020-06-01T25:00:00"),
+        (DataType::Time, "12:00"),
+        (DataType::Boolean, "Yes"),
+        (DataType::BinBase64, "not base64!"),
+        (DataType::BinHex, "abc"),
+        (DataType::BinHex, "+f"),
+        (DataType::UUID, "not-a-uuid"),
+    ];
+    for (data_type, s) in &examples {
+        match Value::parse(data_type, s) {
+            Err(Error::InvalidValue(t, v)) => {
+                assert_eq!(t, *data_type);
+                assert_eq!(v, *s);
+            }
+            other => panic!("{} parsed as {:?}", s, other),
+        }
+    }
+}
+
+#[test]
+fn test_allowed_values() {
+    let scpd: ServiceDescription = WAN_IP_CONNECTION_SCPD.parse().unwrap();
+
+    let protocol = scpd.state_variable("PortMappingProtocol").unwrap();
+    assert_eq!(protocol.parse("TCP").unwrap(), Value::String("TCP".into()));
+    assert!(matches!(
+        protocol.parse("tcp"),
+        Err(Error::DisallowedValue(name, value)) if name == "PortMappingProtocol" && value == "tcp"
+    ));
+
+    let port = scpd.state_variable("InternalPort").unwrap();
+    assert!(port.parse("65535").is_ok());
+    assert!(matches!(port.parse("0"), Err(Error::DisallowedValue(..))));
+    assert!(matches!(
+        port.check(&Value::UI4(80)),
+        Err(Error::InvalidValue(DataType::UI2, _))
+    ));
+
+    let lease = scpd.state_variable("PortMappingLeaseDuration").unwrap();
+    assert!(lease.parse("3600").is_ok());
+    assert!(matches!(
+        lease.parse("604801"),
+        Err(Error::DisallowedValue(..))
+    ));
+}
+
+#[test]
+fn test_check_arguments() {
+    let scpd: ServiceDescription = WAN_IP_CONNECTION_SCPD.parse().unwrap();
+    let arguments = [
+        ("NewRemoteHost", Value::String("".into())),
+        ("NewExternalPort", Value::UI2(8080)),
+        ("NewProtocol", Value::String("TCP".into())),
+    ];
+    scpd.check_arguments("DeletePortMapping", &arguments)
+        .unwrap();
+
+    assert!(matches!(
+        scpd.check_arguments("DeletePortMapping", &arguments[..2]),
+        Err(Error::WrongArguments(action, names)) if action == "DeletePortMapping" && names.len() == 3
+    ));
+    assert!(matches!(
+        scpd.check_arguments(
+            "DeletePortMapping",
+            &[
+                arguments[1].clone(),
+                arguments[0].clone(),
+                arguments[2].clone(),
+            ]
+        ),
+        Err(Error::WrongArguments(..))
+    ));
+    assert!(matches!(
+        scpd.check_arguments(
+            "DeletePortMapping",
+            &[
+                arguments[0].clone(),
+                arguments[1].clone(),
+                ("NewProtocol", Value::String("SCTP".into())),
+            ]
+        ),
+        Err(Error::DisallowedValue(..))
+    ));
+    assert!(matches!(
+        scpd.check_arguments("Explode", &[]),
+        Err(Error::UnknownAction(action)) if action == "Explode"
+    ));
+}
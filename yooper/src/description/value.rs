@@ -0,0 +1,491 @@
+//! Values of state variables and action arguments, as UPnP writes them on the wire
+use std::fmt;
+
+use uuid::Uuid;
+
+use super::scpd::{AllowedValueRange, Argument, DataType, ServiceDescription, StateVariable};
+use crate::Error;
+
+/// A value of one of the UPnP [data types](enum.DataType.html).
+/// [parse](#method.parse) reads the wire representation, and `Display` writes it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    UI1(u8),
+    UI2(u16),
+    UI4(u32),
+    UI8(u64),
+    I1(i8),
+    I2(i16),
+    I4(i32),
+    I8(i64),
+    Int(i64),
+    R4(f32),
+    R8(f64),
+    Number(f64),
+    /// In ten-thousandths, since it's at most 14 digits before the decimal point and 4 after
+    Fixed14_4(i64),
+    Float(f64),
+    Char(char),
+    String(String),
+    Date(Date),
+    DateTime(Date, Option<Time>),
+    DateTimeTZ(Date, Option<Time>, Option<TimeZone>),
+    Time(Time),
+    TimeTZ(Time, Option<TimeZone>),
+    /// Written as `1` or `0`, but `true`, `yes`, `false` and `no` are read too
+    Boolean(bool),
+    BinBase64(Vec<u8>),
+    BinHex(Vec<u8>),
+    /// Not checked, since URIs can be relative
+    URI(String),
+    UUID(Uuid),
+    /// A vendor-defined type, by name, and its value
+    Other(String, String),
+}
+
+/// A calendar date, like `2020-06-01`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+/// A time of day, like `13:30:00`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Time {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// The zone of a `dateTime.tz` or `time.tz`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TimeZone {
+    /// `Z`
+    UTC,
+    /// Minutes east of UTC, like `+01:00`
+    Offset(i16),
+}
+
+impl Value {
+    /// Read the wire representation of a value of type `data_type`
+    pub fn parse(data_type: &DataType, s: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidValue(data_type.clone(), s.to_owned());
+
+        Ok(match data_type {
+            DataType::UI1 => Self::UI1(s.parse().map_err(|_| invalid())?),
+            DataType::UI2 => Self::UI2(s.parse().map_err(|_| invalid())?),
+            DataType::UI4 => Self::UI4(s.parse().map_err(|_| invalid())?),
+            DataType::UI8 => Self::UI8(s.parse().map_err(|_| invalid())?),
+            DataType::I1 => Self::I1(s.parse().map_err(|_| invalid())?),
+            DataType::I2 => Self::I2(s.parse().map_err(|_| invalid())?),
+            DataType::I4 => Self::I4(s.parse().map_err(|_| invalid())?),
+            DataType::I8 => Self::I8(s.parse().map_err(|_| invalid())?),
+            DataType::Int => Self::Int(s.parse().map_err(|_| invalid())?),
+            DataType::R4 => Self::R4(finite(s).ok_or_else(invalid)? as f32),
+            DataType::R8 => Self::R8(finite(s).ok_or_else(invalid)?),
+            DataType::Number => Self::Number(finite(s).ok_or_else(invalid)?),
+            DataType::Float => Self::Float(finite(s).ok_or_else(invalid)?),
+            DataType::Fixed14_4 => Self::Fixed14_4(fixed_14_4(s).ok_or_else(invalid)?),
+            DataType::Char => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Self::Char(c),
+                    _ => return Err(invalid()),
+                }
+            }
+            DataType::String => Self::String(s.to_owned()),
+            DataType::Date => Self::Date(Date::parse(s).ok_or_else(invalid)?),
+            DataType::DateTime => {
+                let (date, time) = date_time(s).ok_or_else(invalid)?;
+                Self::DateTime(date, time)
+            }
+            DataType::DateTimeTZ => {
+                let (s, zone) = TimeZone::split(s).ok_or_else(invalid)?;
+                let (date, time) = date_time(s).ok_or_else(invalid)?;
+                if time.is_none() && zone.is_some() {
+                    return Err(invalid());
+                }
+                Self::DateTimeTZ(date, time, zone)
+            }
+            DataType::Time => Self::Time(Time::parse(s).ok_or_else(invalid)?),
+            DataType::TimeTZ => {
+                let (s, zone) = TimeZone::split(s).ok_or_else(invalid)?;
+                Self::TimeTZ(Time::parse(s).ok_or_else(invalid)?, zone)
+            }
+            DataType::Boolean => Self::Boolean(match s {
+                "1" | "true" | "yes" => true,
+                "0" | "false" | "no" => false,
+                _ => return Err(invalid()),
+            }),
+            DataType::BinBase64 => Self::BinBase64(base64::decode(s).map_err(|_| invalid())?),
+            DataType::BinHex => Self::BinHex(from_hex(s).ok_or_else(invalid)?),
+            DataType::URI => Self::URI(s.to_owned()),
+            DataType::UUID => Self::UUID(Uuid::parse_str(s).map_err(|_| invalid())?),
+            DataType::Other(name) => Self::Other(name.clone(), s.to_owned()),
+        })
+    }
+
+    pub fn data_type(&self) -> DataType {
+        match self {
+            Self::UI1(_) => DataType::UI1,
+            Self::UI2(_) => DataType::UI2,
+            Self::UI4(_) => DataType::UI4,
+            Self::UI8(_) => DataType::UI8,
+            Self::I1(_) => DataType::I1,
+            Self::I2(_) => DataType::I2,
+            Self::I4(_) => DataType::I4,
+            Self::I8(_) => DataType::I8,
+            Self::Int(_) => DataType::Int,
+            Self::R4(_) => DataType::R4,
+            Self::R8(_) => DataType::R8,
+            Self::Number(_) => DataType::Number,
+            Self::Fixed14_4(_) => DataType::Fixed14_4,
+            Self::Float(_) => DataType::Float,
+            Self::Char(_) => DataType::Char,
+            Self::String(_) => DataType::String,
+            Self::Date(_) => DataType::Date,
+            Self::DateTime(..) => DataType::DateTime,
+            Self::DateTimeTZ(..) => DataType::DateTimeTZ,
+            Self::Time(_) => DataType::Time,
+            Self::TimeTZ(..) => DataType::TimeTZ,
+            Self::Boolean(_) => DataType::Boolean,
+            Self::BinBase64(_) => DataType::BinBase64,
+            Self::BinHex(_) => DataType::BinHex,
+            Self::URI(_) => DataType::URI,
+            Self::UUID(_) => DataType::UUID,
+            Self::Other(name, _) => DataType::Other(name.clone()),
+        }
+    }
+
+    /// Integer values, exactly
+    fn as_integer(&self) -> Option<i128> {
+        Some(match *self {
+            Self::UI1(v) => v.into(),
+            Self::UI2(v) => v.into(),
+            Self::UI4(v) => v.into(),
+            Self::UI8(v) => v.into(),
+            Self::I1(v) => v.into(),
+            Self::I2(v) => v.into(),
+            Self::I4(v) => v.into(),
+            Self::I8(v) => v.into(),
+            Self::Int(v) => v.into(),
+            _ => return None,
+        })
+    }
+
+    /// Any numeric value, possibly losing precision
+    fn as_float(&self) -> Option<f64> {
+        match *self {
+            Self::R4(v) => Some(v.into()),
+            Self::R8(v) | Self::Number(v) | Self::Float(v) => Some(v),
+            Self::Fixed14_4(v) => Some(v as f64 / 10_000.0),
+            _ => self.as_integer().map(|v| v as f64),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UI1(v) => write!(f, "{}", v),
+            Self::UI2(v) => write!(f, "{}", v),
+            Self::UI4(v) => write!(f, "{}", v),
+            Self::UI8(v) => write!(f, "{}", v),
+            Self::I1(v) => write!(f, "{}", v),
+            Self::I2(v) => write!(f, "{}", v),
+            Self::I4(v) => write!(f, "{}", v),
+            Self::I8(v) | Self::Int(v) => write!(f, "{}", v),
+            Self::R4(v) => write!(f, "{}", v),
+            Self::R8(v) | Self::Number(v) | Self::Float(v) => write!(f, "{}", v),
+            Self::Fixed14_4(v) => {
+                let sign = if *v < 0 { "-" } else { "" };
+                let (whole, fraction) = (v.abs() / 10_000, v.abs() % 10_000);
+                match format!("{:04}", fraction).trim_end_matches('0') {
+                    "" => write!(f, "{}{}", sign, whole),
+                    fraction => write!(f, "{}{}.{}", sign, whole, fraction),
+                }
+            }
+            Self::Char(c) => write!(f, "{}", c),
+            Self::String(s) | Self::URI(s) | Self::Other(_, s) => write!(f, "{}", s),
+            Self::Date(date) => write!(f, "{}", date),
+            Self::DateTime(date, time) => write_date_time(f, date, time, &None),
+            Self::DateTimeTZ(date, time, zone) => write_date_time(f, date, time, zone),
+            Self::Time(time) => write!(f, "{}", time),
+            Self::TimeTZ(time, zone) => {
+                write!(f, "{}", time)?;
+                zone.map_or(Ok(()), |zone| write!(f, "{}", zone))
+            }
+            Self::Boolean(b) => write!(f, "{}", if *b { "1" } else { "0" }),
+            Self::BinBase64(bytes) => write!(f, "{}", base64::encode(bytes)),
+            Self::BinHex(bytes) => bytes.iter().try_for_each(|b| write!(f, "{:02x}", b)),
+            Self::UUID(uuid) => write!(f, "{}", uuid.to_hyphenated_ref()),
+        }
+    }
+}
+
+impl Date {
+    /// `YYYY-MM-DD`
+    fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split('-');
+        let date = Self {
+            year: fixed_digits(parts.next()?, 4)?,
+            month: fixed_digits(parts.next()?, 2)? as u8,
+            day: fixed_digits(parts.next()?, 2)? as u8,
+        };
+        if parts.next().is_some() || date.month == 0 || date.month > 12 {
+            return None;
+        }
+
+        let leap = date.year.is_multiple_of(4)
+            && (!date.year.is_multiple_of(100) || date.year.is_multiple_of(400));
+        let days = match date.month {
+            2 if leap => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        if date.day == 0 || date.day > days {
+            return None;
+        }
+        Some(date)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl Time {
+    /// `HH:MM:SS`
+    fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split(':');
+        let time = Self {
+            hour: fixed_digits(parts.next()?, 2)? as u8,
+            minute: fixed_digits(parts.next()?, 2)? as u8,
+            second: fixed_digits(parts.next()?, 2)? as u8,
+        };
+        if parts.next().is_some() || time.hour > 23 || time.minute > 59 || time.second > 59 {
+            return None;
+        }
+        Some(time)
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+}
+
+impl TimeZone {
+    /// Split a trailing `Z` or `±HH:MM` off a time, if there is one
+    fn split(s: &str) -> Option<(&str, Option<Self>)> {
+        if let Some(rest) = s.strip_suffix('Z') {
+            return Some((rest, Some(Self::UTC)));
+        }
+        // A date's hyphens aren't a zone, but `-HH:MM` after a time is
+        let i = match s.rfind(['+', '-']) {
+            Some(i) if s[..i].contains(':') => i,
+            _ => return Some((s, None)),
+        };
+
+        let (rest, zone) = s.split_at(i);
+        let sign = if zone.starts_with('-') { -1 } else { 1 };
+        let mut parts = zone[1..].split(':');
+        let hours = fixed_digits(parts.next()?, 2)?;
+        let minutes = fixed_digits(parts.next()?, 2)?;
+        if parts.next().is_some() || hours > 14 || minutes > 59 {
+            return None;
+        }
+        Some((
+            rest,
+            Some(Self::Offset(sign * (hours * 60 + minutes) as i16)),
+        ))
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::UTC => write!(f, "Z"),
+            Self::Offset(minutes) => write!(
+                f,
+                "{}{:02}:{:02}",
+                if minutes < 0 { '-' } else { '+' },
+                minutes.abs() / 60,
+                minutes.abs() % 60
+            ),
+        }
+    }
+}
+
+impl ServiceDescription {
+    /// Check `arguments` are exactly the in-arguments of `action`, in order,
+    /// and that each value is allowed for its related state variable
+    pub fn check_arguments(&self, action: &str, arguments: &[(&str, Value)]) -> Result<(), Error> {
+        let action = self
+            .action(action)
+            .ok_or_else(|| Error::UnknownAction(action.to_owned()))?;
+        let expected: Vec<_> = action.in_arguments().collect();
+        let wrong = || {
+            Error::WrongArguments(
+                action.name.clone(),
+                expected.iter().map(|a| a.name.clone()).collect(),
+            )
+        };
+
+        if expected.len() != arguments.len() {
+            return Err(wrong());
+        }
+        for (argument, (name, value)) in expected.iter().zip(arguments) {
+            if argument.name != *name {
+                return Err(wrong());
+            }
+            self.related_state_variable(argument)?.check(value)?;
+        }
+        Ok(())
+    }
+
+    /// The state variable that gives an argument its type
+    pub fn related_state_variable(&self, argument: &Argument) -> Result<&StateVariable, Error> {
+        self.state_variable(&argument.related_state_variable)
+            .ok_or_else(|| {
+                Error::ParseFailure(format!(
+                    "argument {} refers to missing state variable {}",
+                    argument.name, argument.related_state_variable
+                ))
+            })
+    }
+}
+
+impl StateVariable {
+    /// Read a value of this variable's type, checking it's allowed
+    pub fn parse(&self, s: &str) -> Result<Value, Error> {
+        let value = Value::parse(&self.data_type, s)?;
+        self.check(&value)?;
+        Ok(value)
+    }
+
+    /// Check a value has this variable's type,
+    /// and is in its `allowedValueList` and `allowedValueRange` if it has them
+    pub fn check(&self, value: &Value) -> Result<(), Error> {
+        let disallowed = || Error::DisallowedValue(self.name.clone(), value.to_string());
+
+        if value.data_type() != self.data_type {
+            return Err(Error::InvalidValue(
+                self.data_type.clone(),
+                value.to_string(),
+            ));
+        }
+        if !self.allowed_values.is_empty() && !self.allowed_values.contains(&value.to_string()) {
+            return Err(disallowed());
+        }
+        match &self.allowed_range {
+            Some(range) if !self.in_range(range, value)? => Err(disallowed()),
+            _ => Ok(()),
+        }
+    }
+
+    fn in_range(&self, range: &AllowedValueRange, value: &Value) -> Result<bool, Error> {
+        let bound = |s: &str| Value::parse(&self.data_type, s.trim());
+        let minimum = bound(&range.minimum)?;
+        let maximum = bound(&range.maximum)?;
+        let step = range.step.as_deref().map(bound).transpose()?;
+
+        if let (Some(v), Some(min), Some(max)) = (
+            value.as_integer(),
+            minimum.as_integer(),
+            maximum.as_integer(),
+        ) {
+            let step = step.and_then(|s| s.as_integer()).filter(|s| *s > 0);
+            return Ok(v >= min && v <= max && step.is_none_or(|s| (v - min) % s == 0));
+        }
+
+        match (value.as_float(), minimum.as_float(), maximum.as_float()) {
+            (Some(v), Some(min), Some(max)) => {
+                let step = step.and_then(|s| s.as_float()).filter(|s| *s > 0.0);
+                let on_step = |s: f64| {
+                    let steps = (v - min) / s;
+                    (steps - steps.round()).abs() < 1e-9
+                };
+                Ok(v >= min && v <= max && step.is_none_or(on_step))
+            }
+            // Ranges only apply to numbers
+            _ => Ok(true),
+        }
+    }
+}
+
+/// Numbers Rust reads, but UPnP doesn't, like `inf` and `NaN`
+fn finite(s: &str) -> Option<f64> {
+    s.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// A decimal with at most 14 digits before the point and 4 after, in ten-thousandths
+fn fixed_14_4(s: &str) -> Option<i64> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (whole, fraction) = match digits.find('.') {
+        Some(i) => (&digits[..i], &digits[i + 1..]),
+        None => (digits, ""),
+    };
+    if whole.is_empty()
+        || whole.len() > 14
+        || fraction.len() > 4
+        || !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let value =
+        whole.parse::<i64>().ok()? * 10_000 + format!("{:0<4}", fraction).parse::<i64>().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// Exactly `len` ASCII digits
+fn fixed_digits(s: &str, len: usize) -> Option<u16> {
+    if s.len() != len || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// A date, optionally followed by `T` and a time
+fn date_time(s: &str) -> Option<(Date, Option<Time>)> {
+    match s.find('T') {
+        Some(i) => Some((Date::parse(&s[..i])?, Some(Time::parse(&s[i + 1..])?))),
+        None => Some((Date::parse(s)?, None)),
+    }
+}
+
+fn write_date_time(
+    f: &mut fmt::Formatter,
+    date: &Date,
+    time: &Option<Time>,
+    zone: &Option<TimeZone>,
+) -> fmt::Result {
+    write!(f, "{}", date)?;
+    if let Some(time) = time {
+        write!(f, "T{}", time)?;
+    }
+    zone.map_or(Ok(()), |zone| write!(f, "{}", zone))
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
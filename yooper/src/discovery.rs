@@ -16,6 +16,8 @@ use tokio::{
 use tokio_util::udp::UdpFramed;
 use uuid::{self, Uuid};
 
+#[cfg(feature = "description")]
+use crate::description::{self, DescribedDevice, HttpFetcher};
 use crate::{
     ssdp::message::{Codec, MSearch, Message, SearchTarget, UniqueServiceName},
     Error,
@@ -34,6 +36,7 @@ pub struct Discovery {
 }
 
 /// A Device that's responded to a search
+#[derive(Debug)]
 pub struct Device {
     /// version information for the server that responded to the search
     pub server: String,
@@ -46,6 +49,7 @@ pub struct Device {
 }
 
 /// A Service represents a running service on a device
+#[derive(Debug)]
 pub struct Service {
     /// Unique Service Name identifies a unique instance of a device or service.
     pub service_name: UniqueServiceName,
@@ -129,7 +133,20 @@ impl Discovery {
             };
         }
 
-        Ok(map.into_iter().map(|(_k, v)| v).collect())
+        Ok(map.into_values().collect())
+    }
+
+    /// Find all devices on the network like [find](#method.find), then describe them,
+    /// retrieving up to `concurrency` descriptions at a time.
+    /// See [describe_all](../description/fn.describe_all.html)
+    #[cfg(feature = "description")]
+    pub async fn find_described(
+        &mut self,
+        secs: u8,
+        concurrency: usize,
+    ) -> Result<Vec<DescribedDevice>, Error> {
+        let devices = self.find(secs).await?;
+        Ok(description::describe_all(&HttpFetcher::default(), devices, concurrency).await)
     }
 }
 
@@ -15,15 +15,18 @@ pub enum Error {
     #[error("missing required header {0}")]
     MissingHeader(&'static str),
 
-    #[error("Required header {0} had incorrect value")]
-    IncorrectHeader(&'static str),
-
     #[error("Header {0} had a value we couldn't parse ({1})")]
     MalformedHeader(&'static str, String),
 
+    #[error("{1:?} isn't a valid {0}")]
+    UnexpectedValue(&'static str, String),
+
     #[error("Field {0} had a value we couldn't parse ({1})")]
     MalformedField(&'static str, String),
 
+    #[error("Packet contained invalid UTF-8 where text was expected")]
+    InvalidUTF8(#[from] std::str::Utf8Error),
+
     #[error("IO Error {0}")]
     IO(#[from] std::io::Error),
 
@@ -45,6 +48,51 @@ pub enum Error {
     #[error("Couldn't retrieve over http ({0})")]
     HTTPError(#[from] reqwest::Error),
 
+    #[error("Couldn't connect ({0})")]
+    ConnectFailed(reqwest::Error),
+
+    #[error("Timed out {0}")]
+    Timeout(&'static str),
+
+    #[error("Server responded with status {0}")]
+    HTTPStatus(u16),
+
+    #[error("Only http and https URLs are fetched, not {0}")]
+    UnsupportedScheme(String),
+
+    #[error("Document was bigger than the limit of {0} bytes")]
+    TooLarge(usize),
+
     #[error("Couldn't parse an XML document: {0}")]
     XMLError(#[from] serde_xml_rs::Error),
+
+    #[error("Couldn't read an XML document: {0}")]
+    XMLReadError(#[from] xml::reader::Error),
+
+    #[error("Couldn't write an XML document: {0}")]
+    XMLWriteError(#[from] xml::writer::Error),
+
+    #[error("{1:?} isn't a valid {0}")]
+    InvalidValue(crate::description::DataType, String),
+
+    #[error("{1:?} isn't an allowed value of {0}")]
+    DisallowedValue(String, String),
+
+    #[error("No service {0} found")]
+    ServiceNotFound(String),
+
+    #[error("Service has no action {0}")]
+    UnknownAction(String),
+
+    #[error("{0} takes the arguments {1:?}, in that order")]
+    WrongArguments(String, Vec<String>),
+
+    #[error("{0}")]
+    UPnPError(crate::control::UPnPError),
+
+    #[error("Port mapping renewal stopped unexpectedly")]
+    RenewalStopped,
+
+    #[error("Couldn't parse a URL: {0}")]
+    URLError(#[from] url::ParseError),
 }
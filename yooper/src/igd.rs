@@ -0,0 +1,414 @@
+//! Opening ports through home routers, with the Internet Gateway Device's
+//! WANIPConnection or WANPPPConnection service,
+//! or its WANIPv6FirewallControl service for IPv6,
+//! and watching the WAN link they're opened on
+mod firewall;
+mod managed;
+#[cfg(test)]
+mod tests;
+mod wan;
+
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::control::{invoke_with, ErrorCode, StandardError, WANConnectionError};
+use crate::description::{DataType, Device, HttpFetcher, Post, Service, ServiceType, Value};
+use crate::ssdp::packet::HeaderValue;
+use crate::Error;
+
+pub use firewall::{Firewall, FirewallStatus, OpenPinhole, Pinhole};
+pub use managed::{ManagedMapping, Renewal, Status};
+pub use wan::{
+    AccessType, Event, InterfaceConfig, LinkProperties, Monitor, PhysicalLinkStatus, Sample,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, HeaderValue)]
+pub enum Protocol {
+    #[value("TCP")]
+    TCP,
+    #[value("UDP")]
+    UDP,
+}
+
+/// How long a port mapping lasts before the gateway removes it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Lease {
+    /// Until it's deleted, or maybe until the gateway reboots.
+    /// IGD 2 gateways may only allow this for mappings added by hand.
+    Permanent,
+    /// Whole seconds, at least one. IGD 2 gateways may cap this at a week.
+    For(Duration),
+}
+
+impl Lease {
+    fn seconds(self) -> u32 {
+        match self {
+            Self::Permanent => 0,
+            Self::For(d) => d.as_secs().clamp(1, u32::MAX.into()) as u32,
+        }
+    }
+
+    fn from_seconds(seconds: u32) -> Self {
+        match seconds {
+            0 => Self::Permanent,
+            s => Self::For(Duration::from_secs(s.into())),
+        }
+    }
+}
+
+/// A port forwarded from the gateway's external address to a client on the LAN
+#[derive(Debug, PartialEq, Clone)]
+pub struct PortMapping {
+    /// Only forward traffic from this host, or from anyone if None
+    pub remote_host: Option<Ipv4Addr>,
+    pub external_port: u16,
+    pub protocol: Protocol,
+    pub internal_port: u16,
+    pub internal_client: Ipv4Addr,
+    pub enabled: bool,
+    pub description: String,
+    pub lease: Lease,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, HeaderValue)]
+pub enum ConnectionStatus {
+    #[value("Unconfigured")]
+    Unconfigured,
+    #[value("Connecting")]
+    Connecting,
+    #[value("Connected")]
+    Connected,
+    #[value("PendingDisconnect")]
+    PendingDisconnect,
+    #[value("Disconnecting")]
+    Disconnecting,
+    #[value("Disconnected")]
+    Disconnected,
+    #[value(other)]
+    Other(String),
+}
+
+/// The state of the gateway's connection to its ISP
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StatusInfo {
+    pub connection_status: ConnectionStatus,
+    /// Why it last failed to connect, such as `ERROR_NONE`
+    pub last_connection_error: String,
+    /// How long it's been connected
+    pub uptime: Duration,
+}
+
+/// A service and the client to control it with, which each of this module's clients wraps
+#[derive(Clone)]
+struct ServiceClient<P> {
+    service: Service,
+    client: P,
+}
+
+impl<P: Post> ServiceClient<P> {
+    /// The first service in `device`'s tree of a type `predicate` accepts
+    fn find_by<F>(client: P, device: &Device, predicate: F) -> Option<Self>
+    where
+        F: Fn(&ServiceType) -> bool,
+    {
+        let (_, service) = device
+            .walk_services()
+            .find(|(_, s)| predicate(&s.service_type))?;
+        Some(Self {
+            service: service.clone(),
+            client,
+        })
+    }
+
+    async fn invoke(&self, action: &str, arguments: &[(&str, &str)]) -> Result<Out, Error> {
+        invoke_with(&self.client, &self.service, action, arguments)
+            .await
+            .map(Out)
+    }
+}
+
+/// A client for a gateway's WAN connection service
+#[derive(Clone)]
+pub struct Gateway<P = HttpFetcher> {
+    inner: ServiceClient<P>,
+    /// Of the device it was found in, to recognise its SSDP messages
+    uuid: Option<String>,
+}
+
+impl Gateway {
+    /// Find the WAN connection service anywhere in `device`'s tree.
+    /// WANIPConnection:2 is preferred, then WANIPConnection:1, then WANPPPConnection:1.
+    /// The device should come from [describe](../description/fn.describe.html),
+    /// so its URLs are absolute.
+    pub fn find(device: &Device) -> Option<Self> {
+        Self::find_with(HttpFetcher::default(), device)
+    }
+}
+
+impl<P: Post> Gateway<P> {
+    /// Like [find](#method.find), but with your own client
+    pub fn find_with(client: P, device: &Device) -> Option<Self> {
+        let (_, service) = device
+            .walk_services()
+            .filter_map(|(_, s)| preference(&s.service_type).map(|p| (p, s)))
+            .min_by_key(|(p, _)| *p)?;
+        Some(Self {
+            uuid: Some(device.unique_device_name.uuid.clone()),
+            ..Self::with_client(service.clone(), client)
+        })
+    }
+
+    /// Use a service you've already found
+    pub fn with_client(service: Service, client: P) -> Self {
+        Self {
+            inner: ServiceClient { service, client },
+            uuid: None,
+        }
+    }
+
+    pub fn service(&self) -> &Service {
+        &self.inner.service
+    }
+
+    pub fn client(&self) -> &P {
+        &self.inner.client
+    }
+
+    /// Whether this is a WANIPConnection:2 or later, which supports `AddAnyPortMapping`
+    fn is_version_2(&self) -> bool {
+        let t = &self.inner.service.service_type;
+        t.service_type == "WANIPConnection" && t.version.parse::<u32>().is_ok_and(|v| v >= 2)
+    }
+
+    async fn invoke(&self, action: &str, arguments: &[(&str, &str)]) -> Result<Out, Error> {
+        self.inner.invoke(action, arguments).await
+    }
+
+    /// The gateway's address on the internet
+    pub async fn external_ip_address(&self) -> Result<Ipv4Addr, Error> {
+        self.invoke("GetExternalIPAddress", &[])
+            .await?
+            .parse("NewExternalIPAddress")
+    }
+
+    pub async fn status_info(&self) -> Result<StatusInfo, Error> {
+        let out = self.invoke("GetStatusInfo", &[]).await?;
+        Ok(StatusInfo {
+            connection_status: out.parse("NewConnectionStatus")?,
+            last_connection_error: out.get("NewLastConnectionError")?.to_owned(),
+            uptime: Duration::from_secs(out.parse("NewUptime")?),
+        })
+    }
+
+    /// Add a mapping, or replace one the same client made for the same port.
+    /// Fails with `ConflictInMappingEntry` if another client has the port.
+    pub async fn add_port_mapping(&self, mapping: &PortMapping) -> Result<(), Error> {
+        self.add("AddPortMapping", mapping).await.map(|_| ())
+    }
+
+    /// Add a mapping, letting the gateway choose another external port if that one's taken.
+    /// Returns the external port it chose.
+    /// Only WANIPConnection:2 supports this.
+    pub async fn add_any_port_mapping(&self, mapping: &PortMapping) -> Result<u16, Error> {
+        if !self.is_version_2() {
+            return Err(Error::UnknownAction("AddAnyPortMapping".into()));
+        }
+        self.add("AddAnyPortMapping", mapping)
+            .await?
+            .parse("NewReservedPort")
+    }
+
+    async fn add(&self, action: &str, mapping: &PortMapping) -> Result<Out, Error> {
+        let external_port = mapping.external_port.to_string();
+        let internal_port = mapping.internal_port.to_string();
+        let internal_client = mapping.internal_client.to_string();
+        let lease = mapping.lease.seconds().to_string();
+
+        self.invoke(
+            action,
+            &[
+                ("NewRemoteHost", &remote_host(mapping.remote_host)),
+                ("NewExternalPort", &external_port),
+                ("NewProtocol", &mapping.protocol.to_string()),
+                ("NewInternalPort", &internal_port),
+                ("NewInternalClient", &internal_client),
+                ("NewEnabled", if mapping.enabled { "1" } else { "0" }),
+                ("NewPortMappingDescription", &mapping.description),
+                ("NewLeaseDuration", &lease),
+            ],
+        )
+        .await
+    }
+
+    pub async fn delete_port_mapping(
+        &self,
+        remote_host: Option<Ipv4Addr>,
+        external_port: u16,
+        protocol: Protocol,
+    ) -> Result<(), Error> {
+        self.invoke(
+            "DeletePortMapping",
+            &[
+                ("NewRemoteHost", &self::remote_host(remote_host)),
+                ("NewExternalPort", &external_port.to_string()),
+                ("NewProtocol", &protocol.to_string()),
+            ],
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// The mapping at `index` in the gateway's table, or None if the table is shorter
+    pub async fn port_mapping(&self, index: u16) -> Result<Option<PortMapping>, Error> {
+        let out = self
+            .invoke(
+                "GetGenericPortMappingEntry",
+                &[("NewPortMappingIndex", &index.to_string())],
+            )
+            .await;
+        let out = match not_found(out)? {
+            Some(out) => out,
+            None => return Ok(None),
+        };
+
+        Ok(Some(PortMapping {
+            remote_host: out.remote_host()?,
+            external_port: out.parse("NewExternalPort")?,
+            protocol: out.parse("NewProtocol")?,
+            internal_port: out.parse("NewInternalPort")?,
+            internal_client: out.parse("NewInternalClient")?,
+            enabled: out.boolean("NewEnabled")?,
+            description: out.get("NewPortMappingDescription")?.to_owned(),
+            lease: Lease::from_seconds(out.parse("NewLeaseDuration")?),
+        }))
+    }
+
+    /// Every mapping in the gateway's table
+    pub async fn port_mappings(&self) -> Result<Vec<PortMapping>, Error> {
+        let mut mappings = Vec::new();
+        for index in 0..=u16::MAX {
+            match self.port_mapping(index).await {
+                Ok(Some(mapping)) => mappings.push(mapping),
+                Ok(None) => break,
+                // Some gateways end the table with a generic error instead
+                Err(Error::UPnPError(e))
+                    if index > 0
+                        && matches!(
+                            e.kind,
+                            ErrorCode::Standard(StandardError::InvalidArgs)
+                                | ErrorCode::Standard(StandardError::ActionFailed)
+                        ) =>
+                {
+                    break
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(mappings)
+    }
+
+    /// The mapping for an external port, or None if there isn't one
+    pub async fn specific_port_mapping(
+        &self,
+        remote_host: Option<Ipv4Addr>,
+        external_port: u16,
+        protocol: Protocol,
+    ) -> Result<Option<PortMapping>, Error> {
+        let out = self
+            .invoke(
+                "GetSpecificPortMappingEntry",
+                &[
+                    ("NewRemoteHost", &self::remote_host(remote_host)),
+                    ("NewExternalPort", &external_port.to_string()),
+                    ("NewProtocol", &protocol.to_string()),
+                ],
+            )
+            .await;
+        let out = match not_found(out)? {
+            Some(out) => out,
+            None => return Ok(None),
+        };
+
+        Ok(Some(PortMapping {
+            remote_host,
+            external_port,
+            protocol,
+            internal_port: out.parse("NewInternalPort")?,
+            internal_client: out.parse("NewInternalClient")?,
+            enabled: out.boolean("NewEnabled")?,
+            description: out.get("NewPortMappingDescription")?.to_owned(),
+            lease: Lease::from_seconds(out.parse("NewLeaseDuration")?),
+        }))
+    }
+}
+
+/// Lower is better
+fn preference(service_type: &ServiceType) -> Option<u8> {
+    if service_type.vendor_domain.is_some() {
+        return None;
+    }
+    let version = service_type.version.parse::<u32>().ok()?;
+    match service_type.service_type.as_str() {
+        "WANIPConnection" if version >= 2 => Some(0),
+        "WANIPConnection" => Some(1),
+        "WANPPPConnection" => Some(2),
+        _ => None,
+    }
+}
+
+/// Wildcards are empty
+fn remote_host<A: ToString>(host: Option<A>) -> String {
+    host.map_or(String::new(), |h| h.to_string())
+}
+
+/// Treat the errors gateways use for "there's no such mapping" as None
+fn not_found(out: Result<Out, Error>) -> Result<Option<Out>, Error> {
+    match out {
+        Ok(out) => Ok(Some(out)),
+        Err(Error::UPnPError(e))
+            if matches!(
+                e.kind,
+                ErrorCode::WANConnection(WANConnectionError::SpecifiedArrayIndexInvalid)
+                    | ErrorCode::WANConnection(WANConnectionError::NoSuchEntryInArray)
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// An action's out-arguments
+struct Out(Vec<(String, String)>);
+
+impl Out {
+    fn get(&self, name: &'static str) -> Result<&str, Error> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+            .ok_or_else(|| Error::ParseFailure(format!("response is missing {}", name)))
+    }
+
+    fn parse<T: FromStr>(&self, name: &'static str) -> Result<T, Error> {
+        let value = self.get(name)?.trim();
+        value
+            .parse()
+            .map_err(|_| Error::MalformedField(name, value.to_owned()))
+    }
+
+    fn boolean(&self, name: &'static str) -> Result<bool, Error> {
+        match Value::parse(&DataType::Boolean, self.get(name)?.trim())? {
+            Value::Boolean(b) => Ok(b),
+            v => Err(Error::MalformedField(name, v.to_string())),
+        }
+    }
+
+    fn remote_host(&self) -> Result<Option<Ipv4Addr>, Error> {
+        match self.get("NewRemoteHost")?.trim() {
+            "" => Ok(None),
+            _ => self.parse("NewRemoteHost").map(Some),
+        }
+    }
+}
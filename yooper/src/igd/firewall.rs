@@ -0,0 +1,227 @@
+//! Opening IPv6 pinholes, with IGD 2's WANIPv6FirewallControl service.
+//! There's no NAT on IPv6, so rather than mapping a port
+//! you ask the gateway's firewall to let traffic through to a client.
+#[cfg(test)]
+mod tests;
+
+use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+
+use super::{remote_host, Out, Protocol, ServiceClient};
+use crate::description::{Device, HttpFetcher, Post, Service, ServiceType};
+use crate::Error;
+
+/// The protocol number that means any protocol
+const ANY_PROTOCOL: u16 = 65535;
+/// The longest lease WANIPv6FirewallControl allows
+const MAX_LEASE: Duration = Duration::from_secs(86400);
+
+/// Traffic to let through the firewall. Wildcards are None.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Pinhole {
+    pub remote_host: Option<Ipv6Addr>,
+    pub remote_port: Option<u16>,
+    pub internal_client: Ipv6Addr,
+    pub internal_port: Option<u16>,
+    pub protocol: Option<Protocol>,
+}
+
+/// A pinhole the gateway has opened, which closes when its lease runs out
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct OpenPinhole {
+    id: u16,
+    pinhole: Pinhole,
+    lease: Duration,
+    expires: Instant,
+}
+
+impl OpenPinhole {
+    /// What the gateway calls it
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn pinhole(&self) -> &Pinhole {
+        &self.pinhole
+    }
+
+    /// As last requested
+    pub fn lease(&self) -> Duration {
+        self.lease
+    }
+
+    /// When the gateway will close it, unless it's updated before then
+    pub fn expires(&self) -> Instant {
+        self.expires
+    }
+
+    /// How long is left on the lease, which is zero once it's expired
+    pub fn remaining(&self) -> Duration {
+        self.expires.saturating_duration_since(Instant::now())
+    }
+
+    fn renewed(&mut self, lease: Duration) {
+        self.lease = lease;
+        self.expires = Instant::now() + lease;
+    }
+}
+
+/// Whether the gateway will open pinholes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FirewallStatus {
+    /// If not, everything's let through already
+    pub enabled: bool,
+    pub inbound_pinhole_allowed: bool,
+}
+
+/// A client for a gateway's WANIPv6FirewallControl service
+#[derive(Clone)]
+pub struct Firewall<P = HttpFetcher> {
+    inner: ServiceClient<P>,
+}
+
+impl Firewall {
+    /// Find the WANIPv6FirewallControl service anywhere in `device`'s tree.
+    /// The device should come from [describe](../description/fn.describe.html),
+    /// so its URLs are absolute.
+    pub fn find(device: &Device) -> Option<Self> {
+        Self::find_with(HttpFetcher::default(), device)
+    }
+}
+
+impl<P: Post> Firewall<P> {
+    /// Like [find](#method.find), but with your own client
+    pub fn find_with(client: P, device: &Device) -> Option<Self> {
+        let inner = ServiceClient::find_by(client, device, is_firewall_control)?;
+        Some(Self { inner })
+    }
+
+    /// Use a service you've already found
+    pub fn with_client(service: Service, client: P) -> Self {
+        Self {
+            inner: ServiceClient { service, client },
+        }
+    }
+
+    pub fn service(&self) -> &Service {
+        &self.inner.service
+    }
+
+    pub fn client(&self) -> &P {
+        &self.inner.client
+    }
+
+    async fn invoke(&self, action: &str, arguments: &[(&str, &str)]) -> Result<Out, Error> {
+        self.inner.invoke(action, arguments).await
+    }
+
+    pub async fn status(&self) -> Result<FirewallStatus, Error> {
+        let out = self.invoke("GetFirewallStatus", &[]).await?;
+        Ok(FirewallStatus {
+            enabled: out.boolean("FirewallEnabled")?,
+            inbound_pinhole_allowed: out.boolean("InboundPinholeAllowed")?,
+        })
+    }
+
+    /// Open a pinhole for `lease`, which is clamped to between a second and a day.
+    /// Fails with `InboundPinholeNotAllowed` if the gateway won't open any.
+    pub async fn add_pinhole(
+        &self,
+        pinhole: &Pinhole,
+        lease: Duration,
+    ) -> Result<OpenPinhole, Error> {
+        let lease = clamp_lease(lease);
+        let id = self
+            .invoke(
+                "AddPinhole",
+                &[
+                    ("RemoteHost", &remote_host(pinhole.remote_host)),
+                    ("RemotePort", &port(pinhole.remote_port)),
+                    ("InternalClient", &pinhole.internal_client.to_string()),
+                    ("InternalPort", &port(pinhole.internal_port)),
+                    ("Protocol", &protocol(pinhole.protocol)),
+                    ("LeaseTime", &lease.as_secs().to_string()),
+                ],
+            )
+            .await?
+            .parse("UniqueID")?;
+
+        Ok(OpenPinhole {
+            id,
+            pinhole: pinhole.clone(),
+            lease,
+            expires: Instant::now() + lease,
+        })
+    }
+
+    /// Extend the pinhole's lease to `lease` from now, clamped like [add_pinhole](#method.add_pinhole)
+    pub async fn update_pinhole(
+        &self,
+        pinhole: &mut OpenPinhole,
+        lease: Duration,
+    ) -> Result<(), Error> {
+        let lease = clamp_lease(lease);
+        self.invoke(
+            "UpdatePinhole",
+            &[
+                ("UniqueID", &pinhole.id.to_string()),
+                ("NewLeaseTime", &lease.as_secs().to_string()),
+            ],
+        )
+        .await?;
+        pinhole.renewed(lease);
+        Ok(())
+    }
+
+    pub async fn delete_pinhole(&self, pinhole: &OpenPinhole) -> Result<(), Error> {
+        self.invoke("DeletePinhole", &[("UniqueID", &pinhole.id.to_string())])
+            .await
+            .map(|_| ())
+    }
+
+    /// How many packets have come through the pinhole
+    pub async fn pinhole_packets(&self, pinhole: &OpenPinhole) -> Result<u32, Error> {
+        self.invoke(
+            "GetPinholePackets",
+            &[("UniqueID", &pinhole.id.to_string())],
+        )
+        .await?
+        .parse("PinholePackets")
+    }
+
+    /// Whether traffic is actually getting through the pinhole.
+    /// Fails with `NoPacketSent` if none has been tried yet.
+    pub async fn check_pinhole_working(&self, pinhole: &OpenPinhole) -> Result<bool, Error> {
+        self.invoke(
+            "CheckPinholeWorking",
+            &[("UniqueID", &pinhole.id.to_string())],
+        )
+        .await?
+        .boolean("IsWorking")
+    }
+}
+
+fn is_firewall_control(service_type: &ServiceType) -> bool {
+    service_type.vendor_domain.is_none()
+        && service_type.service_type == "WANIPv6FirewallControl"
+        && service_type.version.parse::<u32>().is_ok()
+}
+
+fn clamp_lease(lease: Duration) -> Duration {
+    Duration::from_secs(lease.as_secs().clamp(1, MAX_LEASE.as_secs()))
+}
+
+/// Wildcards are 0
+fn port(port: Option<u16>) -> String {
+    port.unwrap_or(0).to_string()
+}
+
+/// IANA protocol numbers
+fn protocol(protocol: Option<Protocol>) -> String {
+    match protocol {
+        Some(Protocol::TCP) => 6,
+        Some(Protocol::UDP) => 17,
+        None => ANY_PROTOCOL,
+    }
+    .to_string()
+}
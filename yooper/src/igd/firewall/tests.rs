@@ -0,0 +1,194 @@
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use url::Url;
+
+use super::*;
+use crate::control::{ErrorCode, FirewallControlError};
+use crate::description::{Description, Response};
+use crate::igd::tests::{fake_soap, router};
+use crate::ssdp::packet::Headers;
+
+/// A firewall that keeps its pinholes in memory, as (id, lease, packets)
+#[derive(Default)]
+struct FakeFirewall {
+    pinholes: Mutex<Vec<(u16, u64, u32)>>,
+    next_id: Mutex<u16>,
+}
+
+impl FakeFirewall {
+    fn handle(&self, action: &str, args: &[(String, String)]) -> Result<String, u16> {
+        let arg = |name: &str| {
+            args.iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+                .ok_or(402u16)
+        };
+        let number = |name: &str| arg(name)?.parse::<u64>().map_err(|_| 600u16);
+        let mut pinholes = self.pinholes.lock().unwrap();
+        let find = |pinholes: &[(u16, u64, u32)]| -> Result<usize, u16> {
+            let id = number("UniqueID")? as u16;
+            pinholes.iter().position(|p| p.0 == id).ok_or(704)
+        };
+
+        match action {
+            "GetFirewallStatus" => Ok(
+                "<FirewallEnabled>1</FirewallEnabled><InboundPinholeAllowed>1</InboundPinholeAllowed>"
+                    .into(),
+            ),
+            "AddPinhole" => {
+                if !arg("RemoteHost")?.is_empty() {
+                    arg("RemoteHost")?
+                        .parse::<Ipv6Addr>()
+                        .map_err(|_| 600u16)?;
+                }
+                arg("InternalClient")?
+                    .parse::<Ipv6Addr>()
+                    .map_err(|_| 600u16)?;
+                if !matches!(number("Protocol")?, 6 | 17 | 65535) {
+                    return Err(705);
+                }
+                let lease = number("LeaseTime")?;
+                if !(1..=86400).contains(&lease) {
+                    return Err(601);
+                }
+
+                let mut next_id = self.next_id.lock().unwrap();
+                *next_id += 1;
+                pinholes.push((*next_id, lease, 0));
+                Ok(format!("<UniqueID>{}</UniqueID>", next_id))
+            }
+            "UpdatePinhole" => {
+                let i = find(&pinholes)?;
+                pinholes[i].1 = number("NewLeaseTime")?;
+                Ok(String::new())
+            }
+            "DeletePinhole" => {
+                let i = find(&pinholes)?;
+                pinholes.remove(i);
+                Ok(String::new())
+            }
+            "GetPinholePackets" => {
+                let i = find(&pinholes)?;
+                Ok(format!("<PinholePackets>{}</PinholePackets>", pinholes[i].2))
+            }
+            "CheckPinholeWorking" => match pinholes[find(&pinholes)?].2 {
+                0 => Err(709),
+                _ => Ok("<IsWorking>1</IsWorking>".into()),
+            },
+            _ => Err(401),
+        }
+    }
+}
+
+impl Post for FakeFirewall {
+    fn post<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+        body: Bytes,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        fake_soap(url, headers, &body, |action, arguments| {
+            self.handle(action, arguments)
+        })
+    }
+}
+
+/// The example router, with a firewall beside its WANIPConnection
+fn ipv6_router() -> Device {
+    let mut document = String::new();
+    for line in include_str!("../../description/testdata/igd.xml").lines() {
+        document.push_str(line);
+        if line.contains("<SCPDURL>/ipc.xml</SCPDURL>") {
+            document.push_str(
+                "</service><service>\
+                 <serviceType>urn:schemas-upnp-org:service:WANIPv6FirewallControl:1</serviceType>\
+                 <serviceId>urn:upnp-org:serviceId:WANIPv6FirewallControl</serviceId>\
+                 <controlURL>/fwc</controlURL>\
+                 <eventSubURL>/fwc/events</eventSubURL>\
+                 <SCPDURL>/fwc.xml</SCPDURL>",
+            );
+        }
+    }
+    let mut description = Description::from_slice(document.as_bytes()).unwrap();
+    description
+        .resolve_urls(&Url::parse("http://192.168.7.1:1900/igd.xml").unwrap())
+        .unwrap();
+    description.device
+}
+
+fn pinhole() -> Pinhole {
+    Pinhole {
+        remote_host: None,
+        remote_port: None,
+        internal_client: "2001:db8::42".parse().unwrap(),
+        internal_port: Some(8080),
+        protocol: Some(Protocol::TCP),
+    }
+}
+
+fn firewall_code(result: Result<impl std::fmt::Debug, Error>) -> FirewallControlError {
+    match result {
+        Err(Error::UPnPError(e)) => match e.kind {
+            ErrorCode::FirewallControl(code) => code,
+            kind => panic!("expected a firewall error, got {:?}", kind),
+        },
+        other => panic!("expected a UPnP error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_find() {
+    let firewall = Firewall::find_with(FakeFirewall::default(), &ipv6_router()).unwrap();
+    assert_eq!(
+        firewall.service().control_url,
+        "http://192.168.7.1:1900/fwc"
+    );
+
+    assert!(Firewall::find_with(FakeFirewall::default(), &router(2)).is_none());
+}
+
+#[tokio::test]
+async fn test_pinholes() {
+    let firewall = Firewall::find_with(FakeFirewall::default(), &ipv6_router()).unwrap();
+    assert_eq!(
+        firewall.status().await.unwrap(),
+        FirewallStatus {
+            enabled: true,
+            inbound_pinhole_allowed: true,
+        }
+    );
+
+    let mut open = firewall
+        .add_pinhole(&pinhole(), Duration::from_secs(3600))
+        .await
+        .unwrap();
+    assert_eq!(open.id(), 1);
+    assert_eq!(open.pinhole(), &pinhole());
+    assert_eq!(open.lease(), Duration::from_secs(3600));
+    assert!(open.remaining() > Duration::from_secs(3590));
+    assert_eq!(firewall.pinhole_packets(&open).await.unwrap(), 0);
+    assert_eq!(
+        firewall_code(firewall.check_pinhole_working(&open).await),
+        FirewallControlError::NoPacketSent
+    );
+
+    // Leases are clamped to what the service allows
+    firewall
+        .update_pinhole(&mut open, Duration::from_secs(7 * 86400))
+        .await
+        .unwrap();
+    assert_eq!(open.lease(), Duration::from_secs(86400));
+    assert_eq!(firewall.client().pinholes.lock().unwrap()[0].1, 86400);
+
+    firewall.client().pinholes.lock().unwrap()[0].2 = 3;
+    assert_eq!(firewall.pinhole_packets(&open).await.unwrap(), 3);
+    assert!(firewall.check_pinhole_working(&open).await.unwrap());
+
+    firewall.delete_pinhole(&open).await.unwrap();
+    assert_eq!(
+        firewall_code(firewall.delete_pinhole(&open).await),
+        FirewallControlError::NoSuchEntry
+    );
+}
@@ -0,0 +1,222 @@
+//! Keeping a port mapping open for as long as you need it
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot};
+use tokio::time;
+
+use super::{Gateway, Lease, PortMapping};
+use crate::control::{ErrorCode, WANConnectionError};
+use crate::description::Post;
+use crate::ssdp::message::Message;
+use crate::Error;
+
+/// Renewing more often than this would just keep the gateway busy
+const MIN_RENEWAL: Duration = Duration::from_secs(1);
+
+/// How often a [ManagedMapping](struct.ManagedMapping.html) checks on its gateway
+#[derive(Debug, Clone)]
+pub struct Renewal {
+    /// The longest to go without re-adding the mapping,
+    /// which also notices a new external IP or a mapping lost to a reboot
+    pub check_interval: Duration,
+    /// How long to wait after failing to reach the gateway
+    pub retry_interval: Duration,
+}
+
+impl Default for Renewal {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(300),
+            retry_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What a [ManagedMapping](struct.ManagedMapping.html) last saw
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    /// As last added. The lease is permanent if the gateway refused a finite one.
+    pub mapping: PortMapping,
+    pub external_ip: Option<Ipv4Addr>,
+    /// The gateway's BOOTID.UPNP.ORG, if it's announced one
+    pub boot_id: Option<i32>,
+    /// Why the last attempt to reach the gateway failed, if it did
+    pub last_error: Option<String>,
+}
+
+enum Command {
+    Refresh,
+    Shutdown(oneshot::Sender<Result<(), Error>>),
+}
+
+/// A port mapping that's renewed before its lease runs out,
+/// re-added when the gateway reboots, and deleted when this is dropped.
+///
+/// Renewal happens in a task on the tokio runtime.
+/// Pass SSDP messages to [notice](#method.notice) so reboots are noticed straight away,
+/// rather than at the next [check](struct.Renewal.html#structfield.check_interval).
+pub struct ManagedMapping {
+    status: Arc<Mutex<Status>>,
+    uuid: Option<String>,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl ManagedMapping {
+    /// Add `mapping`, and keep it added.
+    /// Fails if it can't be added the first time.
+    pub async fn new<P: Post + 'static>(
+        gateway: Gateway<P>,
+        mapping: PortMapping,
+    ) -> Result<Self, Error> {
+        Self::with_renewal(gateway, mapping, Renewal::default()).await
+    }
+
+    pub async fn with_renewal<P: Post + 'static>(
+        gateway: Gateway<P>,
+        mapping: PortMapping,
+        renewal: Renewal,
+    ) -> Result<Self, Error> {
+        let uuid = gateway.uuid.clone();
+        let mut maintainer = Maintainer {
+            gateway,
+            renewal,
+            status: Arc::new(Mutex::new(Status {
+                mapping,
+                external_ip: None,
+                boot_id: None,
+                last_error: None,
+            })),
+        };
+        let next = maintainer.maintain().await?;
+
+        let status = maintainer.status.clone();
+        let (commands, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(maintainer.run(receiver, next));
+
+        Ok(Self {
+            status,
+            uuid,
+            commands,
+        })
+    }
+
+    pub fn status(&self) -> Status {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Take note of an SSDP message. If it's from the gateway
+    /// and says it's rebooted, the mapping is added again.
+    pub fn notice(&self, message: &Message) {
+        let (uuid, boot_id) = match message {
+            Message::Available(m) => (&m.unique_service_name.uuid, m.boot_id),
+            Message::SearchResponse(m) => (&m.unique_service_name.uuid, m.boot_id),
+            Message::Update(m) => (&m.unique_service_name.uuid, m.next_boot_id),
+            _ => return,
+        };
+        if self.uuid.as_ref() != Some(uuid) || boot_id.is_none() {
+            return;
+        }
+
+        let mut status = self.status.lock().unwrap();
+        let rebooted = status.boot_id.is_some() && status.boot_id != boot_id;
+        status.boot_id = boot_id;
+        if rebooted {
+            // Only fails if the task's gone, in which case there's nothing to refresh
+            let _ = self.commands.send(Command::Refresh);
+        }
+    }
+
+    /// Delete the mapping, and stop renewing it.
+    /// Dropping does the same, but can't tell you if it worked.
+    pub async fn shutdown(self) -> Result<(), Error> {
+        let (done, result) = oneshot::channel();
+        self.commands
+            .send(Command::Shutdown(done))
+            .map_err(|_| Error::RenewalStopped)?;
+        result.await.map_err(|_| Error::RenewalStopped)?
+    }
+}
+
+/// Lives in the renewal task
+struct Maintainer<P> {
+    gateway: Gateway<P>,
+    renewal: Renewal,
+    status: Arc<Mutex<Status>>,
+}
+
+impl<P: Post> Maintainer<P> {
+    /// Add the mapping again, which renews it.
+    /// Returns how long until it should be added again.
+    async fn maintain(&mut self) -> Result<Duration, Error> {
+        let mut mapping = self.status.lock().unwrap().mapping.clone();
+
+        match self.gateway.add_port_mapping(&mapping).await {
+            Err(Error::UPnPError(e))
+                if e.kind
+                    == ErrorCode::WANConnection(
+                        WANConnectionError::OnlyPermanentLeasesSupported,
+                    )
+                    && mapping.lease != Lease::Permanent =>
+            {
+                mapping.lease = Lease::Permanent;
+                self.gateway.add_port_mapping(&mapping).await?;
+            }
+            result => result?,
+        }
+
+        let next = match mapping.lease {
+            // Half way through the lease the gateway was actually given, so there's time to retry
+            Lease::For(_) => (Duration::from_secs(mapping.lease.seconds().into()) / 2)
+                .min(self.renewal.check_interval)
+                .max(MIN_RENEWAL),
+            Lease::Permanent => self.renewal.check_interval,
+        };
+        // The mapping's what matters, so a gateway that won't say its address can keep the last one
+        let external_ip = self.gateway.external_ip_address().await.ok();
+
+        let mut status = self.status.lock().unwrap();
+        status.mapping = mapping;
+        if external_ip.is_some() {
+            status.external_ip = external_ip;
+        }
+        status.last_error = None;
+        Ok(next)
+    }
+
+    async fn delete(&self) -> Result<(), Error> {
+        let mapping = self.status.lock().unwrap().mapping.clone();
+        self.gateway
+            .delete_port_mapping(mapping.remote_host, mapping.external_port, mapping.protocol)
+            .await
+    }
+
+    async fn run(mut self, mut commands: mpsc::UnboundedReceiver<Command>, mut next: Duration) {
+        loop {
+            tokio::select! {
+                _ = time::delay_for(next) => (),
+                command = commands.recv() => match command {
+                    Some(Command::Refresh) => (),
+                    Some(Command::Shutdown(done)) => {
+                        let _ = done.send(self.delete().await);
+                        return;
+                    }
+                    // The ManagedMapping was dropped
+                    None => {
+                        let _ = self.delete().await;
+                        return;
+                    }
+                },
+            }
+
+            next = match self.maintain().await {
+                Ok(next) => next,
+                Err(e) => {
+                    self.status.lock().unwrap().last_error = Some(e.to_string());
+                    self.renewal.retry_interval
+                }
+            };
+        }
+    }
+}
@@ -0,0 +1,520 @@
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures::future::{BoxFuture, FutureExt};
+use tokio::time;
+use url::Url;
+
+use super::*;
+use crate::description::{read_document, Description, Response};
+use crate::ssdp::message::Message;
+use crate::ssdp::packet::{FromPacket, Headers, Packet, PacketType};
+
+const IGD_EXAMPLE: &str = include_str!("../description/testdata/igd.xml");
+
+/// A gateway that keeps its port mappings in memory
+#[derive(Default)]
+pub(crate) struct FakeGateway {
+    pub mappings: Mutex<Vec<PortMapping>>,
+    /// Every action invoked, in order
+    pub actions: Mutex<Vec<String>>,
+    /// Refuse finite leases, like some IGD 1 gateways
+    pub only_permanent: bool,
+    /// The error code for indexes past the end of the table, if not 713
+    pub end_of_table: Option<u16>,
+    /// Fail GetExternalIPAddress, like a gateway that isn't connected yet
+    pub no_external_ip: bool,
+    /// Fail every action, like a gateway that's busy rebooting
+    pub down: Mutex<bool>,
+    /// Traffic counters, as (sent, received)
+    pub bytes: Mutex<(u64, u64)>,
+    pub link_status: Mutex<&'static str>,
+    /// Connection status and uptime in seconds
+    pub connection: Mutex<(&'static str, u64)>,
+}
+
+impl FakeGateway {
+    pub fn actions(&self) -> Vec<String> {
+        self.actions.lock().unwrap().clone()
+    }
+
+    fn handle(&self, action: &str, args: &[(String, String)]) -> Result<String, u16> {
+        if *self.down.lock().unwrap() {
+            return Err(501);
+        }
+        let arg = |name: &str| {
+            args.iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+                .ok_or(402u16)
+        };
+        let key = || -> Result<(Option<Ipv4Addr>, u16, Protocol), u16> {
+            let host = arg("NewRemoteHost")?;
+            Ok((
+                if host.is_empty() {
+                    None
+                } else {
+                    Some(host.parse().map_err(|_| 600u16)?)
+                },
+                arg("NewExternalPort")?.parse().map_err(|_| 600u16)?,
+                arg("NewProtocol")?.parse().map_err(|_| 600u16)?,
+            ))
+        };
+        let mut mappings = self.mappings.lock().unwrap();
+        let find = |mappings: &[PortMapping], (host, port, protocol)| {
+            mappings.iter().position(|m: &PortMapping| {
+                m.remote_host == host && m.external_port == port && m.protocol == protocol
+            })
+        };
+
+        match action {
+            "GetExternalIPAddress" if self.no_external_ip => Err(501),
+            "GetExternalIPAddress" => Ok(out(&[("NewExternalIPAddress", "203.0.113.7")])),
+            "GetStatusInfo" => {
+                let (status, uptime) = *self.connection.lock().unwrap();
+                Ok(out(&[
+                    ("NewConnectionStatus", status),
+                    ("NewLastConnectionError", "ERROR_NONE"),
+                    ("NewUptime", &uptime.to_string()),
+                ]))
+            }
+            "GetTotalBytesSent" => Ok(out(&[(
+                "NewTotalBytesSent",
+                &self.bytes.lock().unwrap().0.to_string(),
+            )])),
+            "GetTotalBytesReceived" => Ok(out(&[(
+                "NewTotalBytesReceived",
+                &self.bytes.lock().unwrap().1.to_string(),
+            )])),
+            "GetCommonLinkProperties" => Ok(out(&[
+                ("NewWANAccessType", "Cable"),
+                ("NewLayer1UpstreamMaxBitRate", "20000000"),
+                ("NewLayer1DownstreamMaxBitRate", "250000000"),
+                ("NewPhysicalLinkStatus", *self.link_status.lock().unwrap()),
+            ])),
+            "AddPortMapping" | "AddAnyPortMapping" => {
+                let (remote_host, mut external_port, protocol) = key()?;
+                let mapping = PortMapping {
+                    remote_host,
+                    external_port,
+                    protocol,
+                    internal_port: arg("NewInternalPort")?.parse().map_err(|_| 600u16)?,
+                    internal_client: arg("NewInternalClient")?.parse().map_err(|_| 600u16)?,
+                    enabled: arg("NewEnabled")? == "1",
+                    description: arg("NewPortMappingDescription")?.to_owned(),
+                    lease: Lease::from_seconds(
+                        arg("NewLeaseDuration")?.parse().map_err(|_| 600u16)?,
+                    ),
+                };
+                if self.only_permanent && mapping.lease != Lease::Permanent {
+                    return Err(725);
+                }
+
+                while let Some(i) = find(&mappings, (remote_host, external_port, protocol)) {
+                    if mappings[i].internal_client == mapping.internal_client {
+                        mappings.remove(i);
+                    } else if action == "AddAnyPortMapping" {
+                        external_port += 1;
+                    } else {
+                        return Err(718);
+                    }
+                }
+                mappings.push(PortMapping {
+                    external_port,
+                    ..mapping
+                });
+                if action == "AddAnyPortMapping" {
+                    Ok(out(&[("NewReservedPort", &external_port.to_string())]))
+                } else {
+                    Ok(out(&[]))
+                }
+            }
+            "DeletePortMapping" => {
+                let i = find(&mappings, key()?).ok_or(714u16)?;
+                mappings.remove(i);
+                Ok(out(&[]))
+            }
+            "GetGenericPortMappingEntry" => {
+                let index: usize = arg("NewPortMappingIndex")?.parse().map_err(|_| 600u16)?;
+                let m = mappings
+                    .get(index)
+                    .ok_or_else(|| self.end_of_table.unwrap_or(713))?;
+                Ok(out(&[
+                    ("NewRemoteHost", &remote_host(m.remote_host)),
+                    ("NewExternalPort", &m.external_port.to_string()),
+                    ("NewProtocol", &m.protocol.to_string()),
+                    ("NewInternalPort", &m.internal_port.to_string()),
+                    ("NewInternalClient", &m.internal_client.to_string()),
+                    ("NewEnabled", if m.enabled { "1" } else { "0" }),
+                    ("NewPortMappingDescription", &m.description),
+                    ("NewLeaseDuration", &m.lease.seconds().to_string()),
+                ]))
+            }
+            "GetSpecificPortMappingEntry" => {
+                let m = &mappings[find(&mappings, key()?).ok_or(714u16)?];
+                Ok(out(&[
+                    ("NewInternalPort", &m.internal_port.to_string()),
+                    ("NewInternalClient", &m.internal_client.to_string()),
+                    ("NewEnabled", if m.enabled { "true" } else { "false" }),
+                    ("NewPortMappingDescription", &m.description),
+                    ("NewLeaseDuration", &m.lease.seconds().to_string()),
+                ]))
+            }
+            _ => Err(401),
+        }
+    }
+}
+
+/// The inside of an action response
+fn out(arguments: &[(&str, &str)]) -> String {
+    arguments
+        .iter()
+        .map(|(name, value)| format!("<{0}>{1}</{0}>", name, value))
+        .collect()
+}
+
+impl Post for FakeGateway {
+    fn post<'a>(
+        &'a self,
+        url: &'a Url,
+        headers: &'a Headers,
+        body: Bytes,
+    ) -> BoxFuture<'a, Result<Response, Error>> {
+        fake_soap(url, headers, &body, |action, arguments| {
+            self.actions.lock().unwrap().push(action.to_owned());
+            self.handle(action, arguments)
+        })
+    }
+}
+
+/// Answer a control request with what `handle` makes of its action and arguments:
+/// the inside of the response, or an error code for a fault
+pub(crate) fn fake_soap<'a>(
+    url: &Url,
+    headers: &Headers,
+    body: &[u8],
+    handle: impl Fn(&str, &[(String, String)]) -> Result<String, u16>,
+) -> BoxFuture<'a, Result<Response, Error>> {
+    let soap_action = headers.get("soapaction").unwrap().to_str().unwrap();
+    let soap_action = soap_action.trim_matches('"');
+    let (service_type, action) =
+        soap_action.split_at(soap_action.find('#').expect("SOAPAction without #"));
+    let action = &action[1..];
+
+    let envelope = read_document(body, false).unwrap().unwrap();
+    let arguments: Vec<_> = envelope.children[0].children[0]
+        .children
+        .iter()
+        .map(|a| (a.name.clone(), a.text.clone()))
+        .collect();
+
+    let (status, body) = match handle(action, &arguments) {
+        Ok(out) => (
+            200,
+            format!(
+                r#"<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><u:{0}Response xmlns:u="{1}">{2}</u:{0}Response></s:Body></s:Envelope>"#,
+                action, service_type, out
+            ),
+        ),
+        Err(code) => (
+            500,
+            format!(
+                r#"<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{}</errorCode><errorDescription>Error</errorDescription></UPnPError></detail></s:Fault></s:Body></s:Envelope>"#,
+                code
+            ),
+        ),
+    };
+    let response = Response {
+        url: url.clone(),
+        status,
+        headers: Headers::new(),
+        body: body.into(),
+    };
+    async move { Ok(response) }.boxed()
+}
+
+/// The example router, with its WANIPConnection at `version`
+pub(crate) fn router(version: u32) -> Device {
+    let document = IGD_EXAMPLE.replace(
+        "urn:schemas-upnp-org:service:WANIPConnection:1",
+        &format!("urn:schemas-upnp-org:service:WANIPConnection:{}", version),
+    );
+    let mut description = Description::from_slice(document.as_bytes()).unwrap();
+    description
+        .resolve_urls(&Url::parse("http://192.168.7.1:1900/igd.xml").unwrap())
+        .unwrap();
+    description.device
+}
+
+pub(crate) fn mapping(external_port: u16) -> PortMapping {
+    PortMapping {
+        remote_host: None,
+        external_port,
+        protocol: Protocol::TCP,
+        internal_port: 8080,
+        internal_client: Ipv4Addr::new(192, 168, 7, 42),
+        enabled: true,
+        description: "yooper test".into(),
+        lease: Lease::For(Duration::from_secs(3600)),
+    }
+}
+
+#[test]
+fn test_find() {
+    let gateway = Gateway::find_with(FakeGateway::default(), &router(1)).unwrap();
+    assert_eq!(gateway.service().control_url, "http://192.168.7.1:1900/ipc");
+    assert!(!gateway.is_version_2());
+
+    let gateway = Gateway::find_with(FakeGateway::default(), &router(2)).unwrap();
+    assert!(gateway.is_version_2());
+
+    let mut device = router(1);
+    device.devices.clear();
+    assert!(Gateway::find_with(FakeGateway::default(), &device).is_none());
+}
+
+#[tokio::test]
+async fn test_port_mappings() {
+    let gateway = Gateway::find_with(FakeGateway::default(), &router(1)).unwrap();
+
+    assert_eq!(
+        gateway.external_ip_address().await.unwrap(),
+        Ipv4Addr::new(203, 0, 113, 7)
+    );
+
+    gateway.add_port_mapping(&mapping(8080)).await.unwrap();
+    let mut udp = mapping(5353);
+    udp.protocol = Protocol::UDP;
+    udp.lease = Lease::Permanent;
+    udp.remote_host = Some(Ipv4Addr::new(198, 51, 100, 1));
+    gateway.add_port_mapping(&udp).await.unwrap();
+
+    assert_eq!(
+        gateway.port_mappings().await.unwrap(),
+        vec![mapping(8080), udp.clone()]
+    );
+    assert_eq!(
+        gateway
+            .specific_port_mapping(udp.remote_host, 5353, Protocol::UDP)
+            .await
+            .unwrap(),
+        Some(udp.clone())
+    );
+    assert_eq!(
+        gateway
+            .specific_port_mapping(None, 5353, Protocol::TCP)
+            .await
+            .unwrap(),
+        None
+    );
+
+    let mut conflict = mapping(8080);
+    conflict.internal_client = Ipv4Addr::new(192, 168, 7, 43);
+    match gateway.add_port_mapping(&conflict).await {
+        Err(Error::UPnPError(e)) => assert_eq!(
+            e.kind,
+            ErrorCode::WANConnection(WANConnectionError::ConflictInMappingEntry)
+        ),
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+
+    // Version 1 can't pick a port
+    assert!(matches!(
+        gateway.add_any_port_mapping(&conflict).await,
+        Err(Error::UnknownAction(_))
+    ));
+
+    gateway
+        .delete_port_mapping(None, 8080, Protocol::TCP)
+        .await
+        .unwrap();
+    assert_eq!(gateway.port_mappings().await.unwrap(), vec![udp]);
+}
+
+#[tokio::test]
+async fn test_port_mappings_generic_end() {
+    for code in &[402, 501] {
+        let gateway = Gateway::find_with(
+            FakeGateway {
+                end_of_table: Some(*code),
+                ..Default::default()
+            },
+            &router(1),
+        )
+        .unwrap();
+
+        // An empty table can't be told apart from a real failure
+        match gateway.port_mappings().await {
+            Err(Error::UPnPError(e)) => assert_eq!(e.code, *code),
+            other => panic!("expected error {}, got {:?}", code, other),
+        }
+
+        gateway.add_port_mapping(&mapping(8080)).await.unwrap();
+        assert_eq!(gateway.port_mappings().await.unwrap(), vec![mapping(8080)]);
+    }
+}
+
+#[tokio::test]
+async fn test_add_any_port_mapping() {
+    let gateway = Gateway::find_with(FakeGateway::default(), &router(2)).unwrap();
+    gateway.add_port_mapping(&mapping(8080)).await.unwrap();
+
+    let mut other = mapping(8080);
+    other.internal_client = Ipv4Addr::new(192, 168, 7, 43);
+    assert_eq!(gateway.add_any_port_mapping(&other).await.unwrap(), 8081);
+    assert_eq!(
+        gateway
+            .client()
+            .actions()
+            .iter()
+            .filter(|a| *a == "AddAnyPortMapping")
+            .count(),
+        1
+    );
+}
+
+const ROUTER_UUID: &str = "8e5c1a3f-0c5e-4b57-9a3c-7d9f12b0e4a1";
+const ROUTER_USN: &str = "uuid:8e5c1a3f-0c5e-4b57-9a3c-7d9f12b0e4a1::upnp:rootdevice";
+
+/// A router whose mappings the test can still see once they're managed
+fn shared_router(fake: FakeGateway) -> (Arc<FakeGateway>, Gateway<Arc<FakeGateway>>) {
+    let fake = Arc::new(fake);
+    let mut device = router(1);
+    device.unique_device_name.uuid = ROUTER_UUID.into();
+    let gateway = Gateway::find_with(fake.clone(), &device).unwrap();
+    (fake, gateway)
+}
+
+fn router_alive(boot_id: &'static str) -> Message {
+    Message::from_packet(&Packet::new_from_literal(
+        PacketType::Notify,
+        vec![
+            ("host", "239.255.255.250:1900"),
+            ("cache-control", "max-age=1800"),
+            ("location", "http://192.168.7.1:1900/igd.xml"),
+            ("nt", "upnp:rootdevice"),
+            ("nts", "ssdp:alive"),
+            ("server", "Linux/5.4 UPnP/1.1 Router/1.0"),
+            ("usn", ROUTER_USN),
+            ("bootid.upnp.org", boot_id),
+        ],
+    ))
+    .unwrap()
+}
+
+fn slowly() -> Renewal {
+    Renewal {
+        check_interval: Duration::from_secs(3600),
+        retry_interval: Duration::from_secs(3600),
+    }
+}
+
+#[tokio::test]
+async fn test_managed_mapping() {
+    time::pause();
+    let (fake, gateway) = shared_router(FakeGateway::default());
+
+    let managed = ManagedMapping::with_renewal(gateway.clone(), mapping(8080), slowly())
+        .await
+        .unwrap();
+    assert_eq!(*fake.mappings.lock().unwrap(), vec![mapping(8080)]);
+    let status = managed.status();
+    assert_eq!(status.external_ip, Some(Ipv4Addr::new(203, 0, 113, 7)));
+    assert_eq!(status.last_error, None);
+
+    managed.shutdown().await.unwrap();
+    assert!(fake.mappings.lock().unwrap().is_empty());
+
+    // Dropping deletes it too, just not straight away
+    let managed = ManagedMapping::with_renewal(gateway, mapping(8080), slowly())
+        .await
+        .unwrap();
+    drop(managed);
+    time::delay_for(Duration::from_millis(100)).await;
+    assert!(fake.mappings.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_managed_mapping_renews() {
+    time::pause();
+    let (fake, gateway) = shared_router(FakeGateway::default());
+    let adds = || {
+        fake.actions()
+            .iter()
+            .filter(|a| *a == "AddPortMapping")
+            .count()
+    };
+    let mut short = mapping(8080);
+    short.lease = Lease::For(Duration::from_secs(10));
+
+    // Renewed half way through the lease
+    let managed = ManagedMapping::new(gateway.clone(), short).await.unwrap();
+    time::delay_for(Duration::from_millis(4900)).await;
+    assert_eq!(adds(), 1);
+    time::delay_for(Duration::from_millis(200)).await;
+    assert_eq!(adds(), 2);
+    managed.shutdown().await.unwrap();
+
+    // But not more than once a second, however short the lease
+    let mut shortest = mapping(8080);
+    shortest.lease = Lease::For(Duration::from_millis(100));
+    let managed = ManagedMapping::new(gateway, shortest).await.unwrap();
+    time::delay_for(Duration::from_millis(900)).await;
+    assert_eq!(adds(), 3);
+    time::delay_for(Duration::from_millis(200)).await;
+    assert_eq!(adds(), 4);
+    managed.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_managed_mapping_without_external_ip() {
+    let (fake, gateway) = shared_router(FakeGateway {
+        no_external_ip: true,
+        ..Default::default()
+    });
+
+    let managed = ManagedMapping::with_renewal(gateway, mapping(8080), slowly())
+        .await
+        .unwrap();
+    assert_eq!(*fake.mappings.lock().unwrap(), vec![mapping(8080)]);
+    assert_eq!(managed.status().external_ip, None);
+    managed.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_managed_mapping_only_permanent() {
+    let (fake, gateway) = shared_router(FakeGateway {
+        only_permanent: true,
+        ..Default::default()
+    });
+
+    let managed = ManagedMapping::with_renewal(gateway, mapping(8080), slowly())
+        .await
+        .unwrap();
+    assert_eq!(managed.status().mapping.lease, Lease::Permanent);
+    assert_eq!(fake.mappings.lock().unwrap()[0].lease, Lease::Permanent);
+    managed.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_managed_mapping_reboot() {
+    time::pause();
+    let (fake, gateway) = shared_router(FakeGateway::default());
+    let managed = ManagedMapping::with_renewal(gateway, mapping(8080), slowly())
+        .await
+        .unwrap();
+
+    managed.notice(&router_alive("1"));
+    assert_eq!(managed.status().boot_id, Some(1));
+
+    // The router forgets its mappings when it reboots
+    fake.mappings.lock().unwrap().clear();
+    managed.notice(&router_alive("1"));
+    time::delay_for(Duration::from_millis(100)).await;
+    assert!(fake.mappings.lock().unwrap().is_empty());
+
+    managed.notice(&router_alive("2"));
+    time::delay_for(Duration::from_millis(100)).await;
+    assert_eq!(*fake.mappings.lock().unwrap(), vec![mapping(8080)]);
+    assert_eq!(managed.status().boot_id, Some(2));
+}
@@ -0,0 +1,303 @@
+//! Watching a gateway's WAN link, with WANCommonInterfaceConfig's traffic counters
+//! and the connection service's status
+#[cfg(test)]
+mod tests;
+
+use std::time::{Duration, Instant};
+
+use tokio::time;
+
+use super::{ConnectionStatus, Gateway, Out, ServiceClient, StatusInfo};
+use crate::description::{Device, HttpFetcher, Post, Service, ServiceType};
+use crate::ssdp::packet::HeaderValue;
+use crate::Error;
+
+#[derive(Debug, PartialEq, Eq, Clone, HeaderValue)]
+pub enum AccessType {
+    #[value("DSL")]
+    DSL,
+    #[value("POTS")]
+    POTS,
+    #[value("Cable")]
+    Cable,
+    #[value("Ethernet")]
+    Ethernet,
+    #[value(other)]
+    Other(String),
+}
+
+/// Whether the gateway's physical link to its ISP is up
+#[derive(Debug, PartialEq, Eq, Clone, HeaderValue)]
+pub enum PhysicalLinkStatus {
+    #[value("Up")]
+    Up,
+    #[value("Down")]
+    Down,
+    #[value("Initializing")]
+    Initializing,
+    #[value("Unavailable")]
+    Unavailable,
+    #[value(other)]
+    Other(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LinkProperties {
+    pub access_type: AccessType,
+    /// Bits per second
+    pub upstream_max_bit_rate: u32,
+    /// Bits per second
+    pub downstream_max_bit_rate: u32,
+    pub physical_link_status: PhysicalLinkStatus,
+}
+
+/// A client for a gateway's WANCommonInterfaceConfig service
+#[derive(Clone)]
+pub struct InterfaceConfig<P = HttpFetcher> {
+    inner: ServiceClient<P>,
+}
+
+impl InterfaceConfig {
+    /// Find the WANCommonInterfaceConfig service anywhere in `device`'s tree.
+    /// The device should come from [describe](../description/fn.describe.html),
+    /// so its URLs are absolute.
+    pub fn find(device: &Device) -> Option<Self> {
+        Self::find_with(HttpFetcher::default(), device)
+    }
+}
+
+impl<P: Post> InterfaceConfig<P> {
+    /// Like [find](#method.find), but with your own client
+    pub fn find_with(client: P, device: &Device) -> Option<Self> {
+        let inner = ServiceClient::find_by(client, device, is_interface_config)?;
+        Some(Self { inner })
+    }
+
+    /// Use a service you've already found
+    pub fn with_client(service: Service, client: P) -> Self {
+        Self {
+            inner: ServiceClient { service, client },
+        }
+    }
+
+    pub fn service(&self) -> &Service {
+        &self.inner.service
+    }
+
+    pub fn client(&self) -> &P {
+        &self.inner.client
+    }
+
+    async fn invoke(&self, action: &str) -> Result<Out, Error> {
+        self.inner.invoke(action, &[]).await
+    }
+
+    /// Bytes sent to the ISP since some point, usually boot.
+    /// Version 1 counters are 32 bits, so wrap around every 4GiB.
+    pub async fn total_bytes_sent(&self) -> Result<u64, Error> {
+        self.invoke("GetTotalBytesSent")
+            .await?
+            .parse("NewTotalBytesSent")
+    }
+
+    /// Bytes received from the ISP, counted like [total_bytes_sent](#method.total_bytes_sent)
+    pub async fn total_bytes_received(&self) -> Result<u64, Error> {
+        self.invoke("GetTotalBytesReceived")
+            .await?
+            .parse("NewTotalBytesReceived")
+    }
+
+    pub async fn common_link_properties(&self) -> Result<LinkProperties, Error> {
+        let out = self.invoke("GetCommonLinkProperties").await?;
+        Ok(LinkProperties {
+            access_type: out.parse("NewWANAccessType")?,
+            upstream_max_bit_rate: out.parse("NewLayer1UpstreamMaxBitRate")?,
+            downstream_max_bit_rate: out.parse("NewLayer1DownstreamMaxBitRate")?,
+            physical_link_status: out.parse("NewPhysicalLinkStatus")?,
+        })
+    }
+}
+
+fn is_interface_config(service_type: &ServiceType) -> bool {
+    service_type.vendor_domain.is_none()
+        && service_type.service_type == "WANCommonInterfaceConfig"
+        && service_type.version.parse::<u32>().is_ok()
+}
+
+/// Everything a [Monitor](struct.Monitor.html) reads from the gateway at once
+#[derive(Debug, PartialEq, Clone)]
+pub struct Sample {
+    pub taken: Instant,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub link: LinkProperties,
+    /// None if the gateway has no connection service
+    pub status: Option<StatusInfo>,
+}
+
+/// What's changed between samples
+#[derive(Debug, PartialEq, Clone)]
+pub enum Event {
+    /// Bytes per second since the last sample.
+    /// Not reported when the counters were reset, such as when the connection was re-established.
+    Throughput {
+        sent: f64,
+        received: f64,
+    },
+    Link(PhysicalLinkStatus),
+    Connection(ConnectionStatus),
+    /// The connection's uptime went backwards, so it's been re-established
+    Reconnected {
+        uptime: Duration,
+    },
+}
+
+/// Polls a gateway's WAN link, reporting what's changed
+pub struct Monitor<P = HttpFetcher> {
+    interface: InterfaceConfig<P>,
+    gateway: Option<Gateway<P>>,
+    interval: Duration,
+    last: Option<Sample>,
+    /// When the last poll started, whether or not it succeeded
+    polled: Option<time::Instant>,
+}
+
+impl Monitor {
+    /// Watch the WAN link of the gateway described by `device`, every `interval`
+    pub fn find(device: &Device, interval: Duration) -> Option<Self> {
+        Self::find_with(HttpFetcher::default(), device, interval)
+    }
+}
+
+impl<P: Post + Clone> Monitor<P> {
+    /// Like [find](#method.find), but with your own client
+    pub fn find_with(client: P, device: &Device, interval: Duration) -> Option<Self> {
+        let interface = InterfaceConfig::find_with(client.clone(), device)?;
+        Some(Self::new(
+            interface,
+            Gateway::find_with(client, device),
+            interval,
+        ))
+    }
+}
+
+impl<P: Post> Monitor<P> {
+    pub fn new(
+        interface: InterfaceConfig<P>,
+        gateway: Option<Gateway<P>>,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            interface,
+            gateway,
+            interval,
+            last: None,
+            polled: None,
+        }
+    }
+
+    /// The sample the last events came from
+    pub fn last(&self) -> Option<&Sample> {
+        self.last.as_ref()
+    }
+
+    pub async fn sample(&self) -> Result<Sample, Error> {
+        let status = match &self.gateway {
+            Some(gateway) => Some(gateway.status_info().await?),
+            None => None,
+        };
+        Ok(Sample {
+            bytes_sent: self.interface.total_bytes_sent().await?,
+            bytes_received: self.interface.total_bytes_received().await?,
+            link: self.interface.common_link_properties().await?,
+            status,
+            taken: Instant::now(),
+        })
+    }
+
+    /// Wait for the next poll, then report what's changed since the last.
+    /// The first call doesn't wait, and reports the link and connection status as they are.
+    /// Polls are `interval` apart even when they fail, and after an error
+    /// the next call compares against the last sample that succeeded.
+    pub async fn next(&mut self) -> Result<Vec<Event>, Error> {
+        if let Some(polled) = self.polled {
+            time::delay_until(polled + self.interval).await;
+        }
+        self.polled = Some(time::Instant::now());
+        let sample = self.sample().await?;
+        let events = compare(self.last.as_ref(), &sample);
+        self.last = Some(sample);
+        Ok(events)
+    }
+}
+
+fn compare(last: Option<&Sample>, sample: &Sample) -> Vec<Event> {
+    let mut events = Vec::new();
+    let last = match last {
+        Some(last) => last,
+        None => {
+            events.push(Event::Link(sample.link.physical_link_status.clone()));
+            if let Some(status) = &sample.status {
+                events.push(Event::Connection(status.connection_status.clone()));
+            }
+            return events;
+        }
+    };
+
+    let elapsed = sample.taken.duration_since(last.taken).as_secs_f64();
+    // Gateways may start counting again when they reconnect
+    let reconnected = matches!(
+        (&last.status, &sample.status),
+        (Some(before), Some(now)) if now.uptime < before.uptime
+    );
+    // The most the link could have carried, in bytes
+    let most = |bit_rate: u32| (elapsed * f64::from(bit_rate) / 8.0) as u64;
+    let counts = (
+        counted(
+            last.bytes_sent,
+            sample.bytes_sent,
+            most(sample.link.upstream_max_bit_rate),
+        ),
+        counted(
+            last.bytes_received,
+            sample.bytes_received,
+            most(sample.link.downstream_max_bit_rate),
+        ),
+    );
+    if let (false, (Some(sent), Some(received))) = (reconnected, counts) {
+        if elapsed > 0.0 {
+            events.push(Event::Throughput {
+                sent: sent as f64 / elapsed,
+                received: received as f64 / elapsed,
+            });
+        }
+    }
+
+    if sample.link.physical_link_status != last.link.physical_link_status {
+        events.push(Event::Link(sample.link.physical_link_status.clone()));
+    }
+    if let (Some(before), Some(now)) = (&last.status, &sample.status) {
+        if now.connection_status != before.connection_status {
+            events.push(Event::Connection(now.connection_status.clone()));
+        }
+        if now.connection_status == ConnectionStatus::Connected && now.uptime < before.uptime {
+            events.push(Event::Reconnected { uptime: now.uptime });
+        }
+    }
+    events
+}
+
+/// How far a counter went between samples, if it can be told.
+/// A counter going backwards wrapped around at 32 bits only if it was close enough to the end
+/// for the link to have carried the difference, at `most` bytes; otherwise it was reset.
+fn counted(before: u64, now: u64, most: u64) -> Option<u64> {
+    if now >= before {
+        return Some(now - before);
+    }
+    let wrapped = u64::from(u32::MAX).checked_sub(before)? + now + 1;
+    if wrapped <= most {
+        Some(wrapped)
+    } else {
+        None
+    }
+}
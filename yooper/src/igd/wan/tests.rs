@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use super::*;
+use crate::igd::tests::{router, FakeGateway};
+
+fn sample(taken: Instant, bytes: (u64, u64), link: PhysicalLinkStatus, uptime: u64) -> Sample {
+    Sample {
+        taken,
+        bytes_sent: bytes.0,
+        bytes_received: bytes.1,
+        link: LinkProperties {
+            access_type: AccessType::DSL,
+            upstream_max_bit_rate: 1_000_000,
+            downstream_max_bit_rate: 16_000_000,
+            physical_link_status: link,
+        },
+        status: Some(StatusInfo {
+            connection_status: ConnectionStatus::Connected,
+            last_connection_error: "ERROR_NONE".into(),
+            uptime: Duration::from_secs(uptime),
+        }),
+    }
+}
+
+#[test]
+fn test_compare() {
+    let start = Instant::now();
+    let first = sample(start, (1000, 5000), PhysicalLinkStatus::Up, 60);
+    assert_eq!(
+        compare(None, &first),
+        vec![
+            Event::Link(PhysicalLinkStatus::Up),
+            Event::Connection(ConnectionStatus::Connected)
+        ]
+    );
+
+    let second = sample(
+        start + Duration::from_secs(2),
+        (3000, 9000),
+        PhysicalLinkStatus::Up,
+        62,
+    );
+    assert_eq!(
+        compare(Some(&first), &second),
+        vec![Event::Throughput {
+            sent: 1000.0,
+            received: 2000.0
+        }]
+    );
+
+    // A 32 bit counter wrapping around, and the link dropping
+    let third = sample(
+        start + Duration::from_secs(4),
+        (1000, 9000),
+        PhysicalLinkStatus::Down,
+        64,
+    );
+    let mut wrapped = second.clone();
+    wrapped.bytes_sent = u64::from(u32::MAX) - 999;
+    assert_eq!(
+        compare(Some(&wrapped), &third),
+        vec![
+            Event::Throughput {
+                sent: 1000.0,
+                received: 0.0
+            },
+            Event::Link(PhysicalLinkStatus::Down),
+        ]
+    );
+
+    // Counters going backwards further than the link could carry were reset,
+    // so there's no telling how much was sent
+    let mut far = second.clone();
+    far.bytes_sent = u64::from(u32::MAX) - 1_000_000;
+    let mut big = second.clone();
+    big.bytes_sent = 1 << 40;
+    for before in &[far, big] {
+        assert_eq!(
+            compare(Some(before), &third),
+            vec![Event::Link(PhysicalLinkStatus::Down)]
+        );
+    }
+
+    // The connection starting over resets the counters too, even if they happen to have grown
+    let reconnected = sample(
+        start + Duration::from_secs(4),
+        (5000, 10000),
+        PhysicalLinkStatus::Up,
+        3,
+    );
+    assert_eq!(
+        compare(Some(&second), &reconnected),
+        vec![Event::Reconnected {
+            uptime: Duration::from_secs(3)
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_monitor() {
+    let fake = Arc::new(FakeGateway::default());
+    *fake.bytes.lock().unwrap() = (1000, 5000);
+    *fake.link_status.lock().unwrap() = "Up";
+    *fake.connection.lock().unwrap() = ("Connected", 600);
+
+    let interface = InterfaceConfig::find_with(fake.clone(), &router(1)).unwrap();
+    assert_eq!(
+        interface.service().control_url,
+        "http://192.168.7.1:1900/ifc"
+    );
+    assert_eq!(
+        interface.common_link_properties().await.unwrap(),
+        LinkProperties {
+            access_type: AccessType::Cable,
+            upstream_max_bit_rate: 20_000_000,
+            downstream_max_bit_rate: 250_000_000,
+            physical_link_status: PhysicalLinkStatus::Up,
+        }
+    );
+
+    let mut monitor =
+        Monitor::find_with(fake.clone(), &router(1), Duration::from_millis(10)).unwrap();
+    assert_eq!(
+        monitor.next().await.unwrap(),
+        vec![
+            Event::Link(PhysicalLinkStatus::Up),
+            Event::Connection(ConnectionStatus::Connected)
+        ]
+    );
+    assert_eq!(
+        monitor.last().unwrap().status.as_ref().unwrap().uptime,
+        Duration::from_secs(600)
+    );
+
+    *fake.bytes.lock().unwrap() = (2000, 5000);
+    *fake.connection.lock().unwrap() = ("Connected", 601);
+    let events = monitor.next().await.unwrap();
+    match events[..] {
+        [Event::Throughput { sent, received }] => {
+            assert!(sent > 0.0);
+            assert_eq!(received, 0.0);
+        }
+        ref other => panic!("expected throughput, got {:?}", other),
+    }
+
+    // Uptime starting over means the counters might have, so there's no throughput
+    *fake.bytes.lock().unwrap() = (3000, 5000);
+    *fake.connection.lock().unwrap() = ("Disconnected", 0);
+    assert_eq!(
+        monitor.next().await.unwrap(),
+        vec![Event::Connection(ConnectionStatus::Disconnected)]
+    );
+}
+
+#[tokio::test]
+async fn test_monitor_waits_after_errors() {
+    time::pause();
+    let fake = Arc::new(FakeGateway::default());
+    *fake.down.lock().unwrap() = true;
+    let mut monitor =
+        Monitor::find_with(fake.clone(), &router(1), Duration::from_secs(10)).unwrap();
+
+    // Even without a sample to compare against, polls are an interval apart
+    let start = time::Instant::now();
+    let polled_at = |secs| {
+        let elapsed = start.elapsed();
+        elapsed >= Duration::from_secs(secs) && elapsed < Duration::from_secs(secs + 1)
+    };
+    assert!(monitor.next().await.is_err());
+    assert!(polled_at(0));
+    assert!(monitor.next().await.is_err());
+    assert!(polled_at(10));
+
+    *fake.down.lock().unwrap() = false;
+    monitor.next().await.unwrap();
+    assert!(polled_at(20));
+}
@@ -1,9 +1,17 @@
 //! Yooper is a library for discovering and controlling UPnP devices on your network.
-//! The modules represent the phases of UPnP: First Discovery, then Description.
+//! The modules represent the phases of UPnP: First Discovery, then Description, then Control.
+//! `igd` builds on them to open ports through home routers.
+#[cfg(feature = "description")]
+pub mod control;
 #[cfg(feature = "description")]
 pub mod description;
 pub mod discovery;
 mod errors;
+#[cfg(feature = "description")]
+pub mod igd;
 pub mod ssdp;
 
 pub use errors::Error;
+
+// Lets code generated by yooper_derive refer to `::yooper` from inside this crate too
+extern crate self as yooper;
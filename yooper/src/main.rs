@@ -15,12 +15,16 @@ fn validate_secs(v: String) -> Result<(), String> {
     }
 }
 
+fn validate_interval(v: String) -> Result<(), String> {
+    match v.parse::<u64>() {
+        Ok(secs) if secs > 0 => Ok(()),
+        _ => Err("Please specify a whole number of seconds".to_owned()),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    let args = app_from_crate!()
-        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
-        .subcommands(vec![
-            SubCommand::with_name("discover")
+    let discover_command = SubCommand::with_name("discover")
                      .about("discover UPnP devices on network")
                      .arg(Arg::with_name("timeout")
                           .short("t")
@@ -29,17 +33,90 @@ async fn main() -> Result<(), Error> {
                           .default_value("5")
                           .value_name("TIMEOUT")
                           .help("How long to wait for devices on the network to respond. 1..5 seconds per the UPnP spec.")
-                          .validator(validate_secs)),
+                          .validator(validate_secs));
+    #[cfg(feature = "description")]
+    let discover_command = discover_command.arg(
+        Arg::with_name("describe")
+            .short("d")
+            .long("describe")
+            .help("Describe each device found, and print its devices and services"),
+    );
+
+    let args = app_from_crate!()
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommands(vec![
+            discover_command,
             #[cfg(feature = "description")]
             SubCommand::with_name("describe")
                 .about("Describe a UPnP device's capabilities")
-                .arg(Arg::with_name("url").help("The URL to describe").required(true)),
-        ]).get_matches();
+                .arg(
+                    Arg::with_name("url")
+                        .help("The URL to describe")
+                        .required(true),
+                ),
+            #[cfg(feature = "description")]
+            SubCommand::with_name("actions")
+                .about("List the actions a device's service supports")
+                .arg(
+                    Arg::with_name("url")
+                        .help("The URL of the device description")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("service")
+                        .help("The service type or id, such as WANIPConnection")
+                        .required(true),
+                ),
+            #[cfg(feature = "description")]
+            SubCommand::with_name("wan")
+                .about("Watch an Internet Gateway Device's WAN link")
+                .arg(
+                    Arg::with_name("url")
+                        .help("The URL of the gateway's device description")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("interval")
+                        .short("i")
+                        .long("interval")
+                        .takes_value(true)
+                        .default_value("5")
+                        .value_name("SECONDS")
+                        .help("How often to poll the gateway")
+                        .validator(validate_interval),
+                ),
+        ])
+        .get_matches();
 
     match args.subcommand() {
-        ("discover", Some(sub_m)) => discover(sub_m.value_of("timeout").unwrap().parse()?).await,
+        ("discover", Some(sub_m)) => {
+            let secs = sub_m.value_of("timeout").unwrap().parse()?;
+            #[cfg(feature = "description")]
+            {
+                if sub_m.is_present("describe") {
+                    return describe::discover(secs).await;
+                }
+            }
+            discover(secs).await
+        }
         #[cfg(feature = "description")]
         ("describe", Some(sub_m)) => describe::run(sub_m.value_of("url").unwrap()).await,
+        #[cfg(feature = "description")]
+        ("actions", Some(sub_m)) => {
+            describe::actions(
+                sub_m.value_of("url").unwrap(),
+                sub_m.value_of("service").unwrap(),
+            )
+            .await
+        }
+        #[cfg(feature = "description")]
+        ("wan", Some(sub_m)) => {
+            wan::run(
+                sub_m.value_of("url").unwrap(),
+                sub_m.value_of("interval").unwrap().parse()?,
+            )
+            .await
+        }
         _ => unreachable!(),
     }
 }
@@ -58,13 +135,96 @@ async fn discover(secs: u8) -> Result<(), Error> {
 
 #[cfg(feature = "description")]
 mod describe {
-    use yooper::description::{describe, Device};
+    use url::Url;
+    use yooper::description::{
+        describe, describe_file, describe_service, Described, Device, Service,
+    };
+    use yooper::discovery::Discovery;
     use yooper::Error;
 
+    /// How many devices to describe at once
+    const CONCURRENCY: usize = 8;
+
+    pub async fn discover(secs: u8) -> Result<(), Error> {
+        let mut discovery = Discovery::new().await?;
+
+        for found in discovery.find_described(secs, CONCURRENCY).await? {
+            let server = found.discovered.first().map_or("", |d| d.server.as_str());
+            println!("{} at {}", server, found.location);
+            match found.description {
+                Ok(described) => print_device(described.description.device, 1),
+                Err(e) => println!("{}couldn't describe: {}", get_prefix(1), e),
+            }
+        }
+        Ok(())
+    }
+
     pub async fn run(url: &str) -> Result<(), Error> {
-        describe(url).await.and_then(|d| {
-            print_device(d, 0);
-            Ok(())
+        let described = describe_location(url).await?;
+        let spec = &described.description.spec_version;
+        println!(
+            "UPnP/{}.{} from {}",
+            spec.major, spec.minor, described.location
+        );
+        if let Some(server) = &described.server {
+            println!("Server: {}", server);
+        }
+        if let Some(config_id) = described
+            .config_id
+            .or_else(|| described.description.config_id.as_ref()?.parse().ok())
+        {
+            println!("Config ID: {}", config_id);
+        }
+        print_device(described.description.device, 0);
+        Ok(())
+    }
+
+    pub async fn actions(url: &str, name: &str) -> Result<(), Error> {
+        let device = describe_location(url).await?.description.device;
+        let service =
+            find_service(&device, name).ok_or_else(|| Error::ServiceNotFound(name.into()))?;
+
+        let scpd = describe_service(service).await?;
+        let data_type = |variable: &str| {
+            scpd.state_variable(variable)
+                .map_or("?".into(), |v| v.data_type.to_string())
+        };
+        for action in &scpd.action_list {
+            let ins: Vec<String> = action
+                .in_arguments()
+                .map(|a| format!("{}: {}", a.name, data_type(&a.related_state_variable)))
+                .collect();
+            let outs: Vec<String> = action
+                .out_arguments()
+                .map(|a| format!("{}: {}", a.name, data_type(&a.related_state_variable)))
+                .collect();
+            println!(
+                "{}({}) -> ({})",
+                action.name,
+                ins.join(", "),
+                outs.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Describe a device over http(s), or from a description saved to disk
+    pub async fn describe_location(location: &str) -> Result<Described, Error> {
+        match Url::parse(location) {
+            Ok(url) if url.scheme() == "file" => match url.to_file_path() {
+                Ok(path) => describe_file(path).await,
+                Err(_) => Err(Error::MalformedField("location", location.to_owned())),
+            },
+            Ok(_) => describe(location).await,
+            // Not a URL at all, so take it as a path
+            Err(_) => describe_file(location).await,
+        }
+    }
+
+    fn find_service<'a>(device: &'a Device, name: &str) -> Option<&'a Service> {
+        device.walk_services().map(|(_, s)| s).find(|s| {
+            s.service_type.service_type.eq_ignore_ascii_case(name)
+                || s.service_id.service_id.eq_ignore_ascii_case(name)
         })
     }
 
@@ -87,6 +247,16 @@ mod describe {
             device.device_type.device_type,
             device.device_type.version,
         );
+        for icon in device.icons {
+            println!(
+                "{}I {} {}x{} -> {}",
+                get_prefix(indent + 1),
+                icon.mimetype,
+                icon.width,
+                icon.height,
+                icon.url,
+            )
+        }
         for svc in device.services {
             println!(
                 "{}S {}{}:{} -> {}",
@@ -105,3 +275,75 @@ mod describe {
         }
     }
 }
+
+#[cfg(feature = "description")]
+mod wan {
+    use std::time::Duration;
+
+    use super::describe::describe_location;
+    use yooper::igd::{Event, Gateway, Monitor};
+    use yooper::Error;
+
+    pub async fn run(url: &str, interval: u64) -> Result<(), Error> {
+        let device = describe_location(url).await?.description.device;
+        let mut monitor = Monitor::find(&device, Duration::from_secs(interval))
+            .ok_or_else(|| Error::ServiceNotFound("WANCommonInterfaceConfig".into()))?;
+        if let Some(gateway) = Gateway::find(&device) {
+            match gateway.external_ip_address().await {
+                Ok(ip) => println!("External IP: {}", ip),
+                Err(e) => eprintln!("Couldn't get the external IP: {}", e),
+            }
+        }
+
+        let mut described = false;
+        loop {
+            let events = match monitor.next().await {
+                Ok(events) => events,
+                Err(e) => {
+                    eprintln!("Couldn't poll the gateway: {}", e);
+                    continue;
+                }
+            };
+            if !described {
+                if let Some(sample) = monitor.last() {
+                    let link = &sample.link;
+                    println!(
+                        "{} link, up to {} up and {} down",
+                        link.access_type,
+                        bit_rate(link.upstream_max_bit_rate),
+                        bit_rate(link.downstream_max_bit_rate),
+                    );
+                    if let Some(status) = &sample.status {
+                        println!("Uptime: {}s", status.uptime.as_secs());
+                    }
+                    described = true;
+                }
+            }
+
+            for event in events {
+                match event {
+                    Event::Throughput { sent, received } => {
+                        println!("↑ {}  ↓ {}", byte_rate(sent), byte_rate(received))
+                    }
+                    Event::Link(status) => println!("Link {}", status),
+                    Event::Connection(status) => println!("Connection {}", status),
+                    Event::Reconnected { uptime } => {
+                        println!("Reconnected {}s ago", uptime.as_secs())
+                    }
+                }
+            }
+        }
+    }
+
+    fn bit_rate(bits: u32) -> String {
+        format!("{:.1} Mb/s", f64::from(bits) / 1e6)
+    }
+
+    fn byte_rate(bytes: f64) -> String {
+        if bytes >= 1e6 {
+            format!("{:.1} MB/s", bytes / 1e6)
+        } else {
+            format!("{:.1} kB/s", bytes / 1e3)
+        }
+    }
+}
@@ -1,12 +1,12 @@
 //! A set of symbolic representations of SSDP packets
 
 mod codec;
-pub(self) mod types;
+mod types;
 
 use crate::ssdp::packet::{FromHeaders, FromPacket, ToHeaders, ToPacket};
 pub use codec::Codec;
 
-pub use types::{SearchTarget, UniqueServiceName};
+pub use types::{Ext, ManDiscover, NotificationSubType, SearchTarget, UniqueServiceName};
 
 #[derive(ToHeaders, FromHeaders, Debug, PartialEq, Default)]
 pub struct MSearch {
@@ -58,6 +58,8 @@ pub struct Available {
     /// root  device.  Normally  the  host  portion  contains  a  literal
     /// IP  address  rather  than  a  domain  name  in  unmanaged  networks.
     /// Specified  by  UPnP  vendor. Single absolute URL (see RFC 3986)
+    /// Some older devices send this as `AL`
+    #[header(name = "location", alias = "al")]
     pub location: String,
 
     #[header("securelocation.upnp.org")]
@@ -88,6 +90,57 @@ pub struct Available {
     pub search_port: Option<u16>,
 }
 
+#[derive(ToHeaders, FromHeaders, Debug, PartialEq)]
+pub struct Unavailable {
+    pub host: String,
+
+    #[header("nt")]
+    pub notification_type: SearchTarget,
+
+    /// Identifies a unique instance of a device or service.
+    #[header("usn")]
+    pub unique_service_name: UniqueServiceName,
+
+    /// presents the boot instance of the device expressed according to a monotonically increasing value.
+    #[header("bootid.upnp.org")]
+    pub boot_id: Option<i32>,
+
+    /// A number identifying this particular configuration.
+    #[header("configid.upnp.org")]
+    pub config_id: Option<i32>,
+}
+
+#[derive(ToHeaders, FromHeaders, Debug, PartialEq)]
+pub struct Update {
+    pub host: String,
+
+    /// The URL for the UPNP description of the root device
+    pub location: String,
+
+    #[header("nt")]
+    pub notification_type: SearchTarget,
+
+    /// Identifies a unique instance of a device or service.
+    #[header("usn")]
+    pub unique_service_name: UniqueServiceName,
+
+    /// The boot instance the device is currently in
+    #[header("bootid.upnp.org")]
+    pub boot_id: Option<i32>,
+
+    /// A number identifying this particular configuration.
+    #[header("configid.upnp.org")]
+    pub config_id: Option<i32>,
+
+    /// The boot instance the device will use after this update
+    #[header("nextbootid.upnp.org")]
+    pub next_boot_id: Option<i32>,
+
+    /// A port other than 1900 than can be used for queries
+    #[header("searchport.upnp.org")]
+    pub search_port: Option<u16>,
+}
+
 #[derive(ToHeaders, FromHeaders, Debug, PartialEq)]
 pub struct SearchResponse {
     /// Specifies how long this response is valid
@@ -138,10 +191,28 @@ pub enum Message {
     /// Notification that a device has been added to the network
     #[message(reqline = "Notify", nts = "ssdp:alive")]
     Available(Available),
+    /// Notification that a device is leaving the network
+    #[message(reqline = "Notify", nts = "ssdp:byebye")]
+    Unavailable(Unavailable),
+    /// Notification that a device's boot id is about to change
+    #[message(reqline = "Notify", nts = "ssdp:update")]
+    Update(Update),
     /// A response to a search query
     #[message(reqline = "Ok")]
     SearchResponse(SearchResponse),
 }
 
+impl Message {
+    /// The NTS header this message is sent with, if it's a notification
+    pub fn notification_sub_type(&self) -> Option<NotificationSubType> {
+        match self {
+            Self::Available(_) => Some(NotificationSubType::Alive),
+            Self::Unavailable(_) => Some(NotificationSubType::ByeBye),
+            Self::Update(_) => Some(NotificationSubType::Update),
+            Self::MSearch(_) | Self::SearchResponse(_) => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests;
@@ -1,5 +1,6 @@
 use super::*;
-use crate::ssdp::packet::{FromPacket, Packet, PacketType::*, ToPacket};
+use crate::ssdp::packet::{FromPacket, Headers, Packet, PacketType::*, ToPacket};
+use crate::Error;
 
 fn available_packet() -> Packet {
     Packet::new_from_literal(
@@ -53,3 +54,189 @@ fn test_packet_from_availabe() {
     let expected = available_packet();
     assert_eq!(expected, available.to_packet())
 }
+
+#[test]
+fn test_notification_sub_type() {
+    let available = available();
+    let nts = available.notification_sub_type().unwrap();
+    assert_eq!(nts, NotificationSubType::Alive);
+    let packet = available.to_packet();
+    let header = packet.headers.get("nts").unwrap().to_str().unwrap();
+    assert_eq!(header.parse::<NotificationSubType>().unwrap(), nts);
+}
+
+#[derive(ToHeaders, FromHeaders, Debug, PartialEq)]
+struct Extensions {
+    #[header("opt")]
+    options: Vec<String>,
+}
+
+#[test]
+fn test_multiple_headers() {
+    let headers: Headers = vec![
+        ("OPT", "\"http://schemas.upnp.org/upnp/1/0/\"; ns=01"),
+        ("01-NLS", "b9200ebb-736d-4b93-bf03-835149d13983"),
+        ("OPT", "\"http://example.com/ext/\"; ns=02"),
+    ]
+    .into_iter()
+    .collect();
+
+    let expected = Extensions {
+        options: vec![
+            "\"http://schemas.upnp.org/upnp/1/0/\"; ns=01".into(),
+            "\"http://example.com/ext/\"; ns=02".into(),
+        ],
+    };
+    let parsed = Extensions::from_headers(&headers).unwrap();
+    assert_eq!(expected, parsed);
+
+    let opts: Vec<_> = parsed.to_headers().get_all("opt").cloned().collect();
+    assert_eq!(opts, headers.get_all("opt").cloned().collect::<Vec<_>>());
+}
+
+mod max_age {
+    use crate::Error;
+
+    pub fn from_header(s: &str) -> Result<u32, Error> {
+        s.strip_prefix("max-age=")
+            .ok_or_else(|| Error::MalformedHeader("cache-control", s.to_owned()))?
+            .parse()
+            .map_err(Error::from)
+    }
+
+    pub fn to_header(v: &u32) -> String {
+        format!("max-age={}", v)
+    }
+}
+
+#[derive(ToHeaders, FromHeaders, Debug, PartialEq)]
+struct Hooks {
+    #[header(name = "location", alias = "al")]
+    location: String,
+    #[header(name = "cache-control", with = max_age, default = 1800)]
+    max_age: u32,
+    #[header("mx", default)]
+    max_wait: u8,
+}
+
+#[test]
+fn test_header_alias() {
+    let headers: Headers = vec![("AL", "http://192.168.7.1:1900/igd.xml")]
+        .into_iter()
+        .collect();
+
+    let parsed = Hooks::from_headers(&headers).unwrap();
+    assert_eq!(parsed.location, "http://192.168.7.1:1900/igd.xml");
+
+    // the primary name is always used on output
+    let out = parsed.to_headers();
+    assert_eq!(
+        out.get("location").unwrap(),
+        "http://192.168.7.1:1900/igd.xml"
+    );
+    assert!(!out.contains_key("al"));
+}
+
+#[test]
+fn test_header_default_and_with() {
+    let headers: Headers = vec![("LOCATION", "http://192.168.7.1:1900/igd.xml")]
+        .into_iter()
+        .collect();
+    let parsed = Hooks::from_headers(&headers).unwrap();
+    assert_eq!(parsed.max_age, 1800);
+    assert_eq!(parsed.max_wait, 0);
+
+    let headers: Headers = vec![
+        ("LOCATION", "http://192.168.7.1:1900/igd.xml"),
+        ("CACHE-CONTROL", "max-age=60"),
+    ]
+    .into_iter()
+    .collect();
+    let parsed = Hooks::from_headers(&headers).unwrap();
+    assert_eq!(parsed.max_age, 60);
+    assert_eq!(
+        parsed.to_headers().get("cache-control").unwrap(),
+        "max-age=60"
+    );
+
+    let headers: Headers = vec![
+        ("LOCATION", "http://192.168.7.1:1900/igd.xml"),
+        ("CACHE-CONTROL", "no-cache"),
+    ]
+    .into_iter()
+    .collect();
+    match Hooks::from_headers(&headers).unwrap_err() {
+        Error::MalformedHeader("cache-control", v) if v == "no-cache" => (),
+        e => panic!("Didn't get the error we assumed! {:?}", e),
+    };
+}
+
+#[test]
+fn test_unavailable_from_packet() {
+    let packet = Packet::new_from_literal(
+        Notify,
+        vec![
+            ("host", "239.255.255.250:1900"),
+            ("nt", "upnp:rootdevice"),
+            ("nts", "ssdp:byebye"),
+            (
+                "usn",
+                "uuid:07853410-ccef-9e3c-de6a-410b371182eb::upnp:rootdevice",
+            ),
+            ("bootid.upnp.org", "12"),
+        ],
+    );
+    let expected = Message::Unavailable(Unavailable {
+        host: "239.255.255.250:1900".into(),
+        notification_type: SearchTarget::RootDevice,
+        unique_service_name: UniqueServiceName {
+            uuid: "07853410-ccef-9e3c-de6a-410b371182eb".into(),
+            search_target: Some(SearchTarget::RootDevice),
+        },
+        boot_id: Some(12),
+        config_id: None,
+    });
+
+    assert_eq!(expected, Message::from_packet(&packet).unwrap());
+    assert_eq!(packet, expected.to_packet());
+}
+
+#[test]
+fn test_unknown_nts() {
+    let packet = Packet::new_from_literal(
+        Notify,
+        vec![("host", "239.255.255.250:1900"), ("nts", "ssdp:propchange")],
+    );
+    match Message::from_packet(&packet).unwrap_err() {
+        Error::UnknownPacket => (),
+        e => panic!("Didn't get the error we assumed! {:?}", e),
+    };
+}
+
+#[test]
+fn test_header_values() {
+    assert_eq!(ManDiscover.to_string(), "\"ssdp:discover\"");
+    assert_eq!("ssdp:discover".parse::<ManDiscover>().unwrap(), ManDiscover);
+    assert_eq!(
+        "\"ssdp:discover\"".parse::<ManDiscover>().unwrap(),
+        ManDiscover
+    );
+
+    assert_eq!("".parse::<Ext>().unwrap(), Ext);
+    match "yes".parse::<Ext>().unwrap_err() {
+        Error::UnexpectedValue("Ext", v) if v == "yes" => (),
+        e => panic!("Didn't get the error we assumed! {:?}", e),
+    };
+
+    assert_eq!(
+        "ssdp:byebye".parse::<NotificationSubType>().unwrap(),
+        NotificationSubType::ByeBye
+    );
+    assert_eq!(
+        NotificationSubType::PropChange.to_string(),
+        "upnp:propchange"
+    );
+    let other: NotificationSubType = "ssdp:other".parse().unwrap();
+    assert_eq!(other, NotificationSubType::Other("ssdp:other".into()));
+    assert_eq!(other.to_string(), "ssdp:other");
+}
@@ -1,48 +1,43 @@
+use crate::ssdp::packet::HeaderValue;
 use crate::Error;
+use std::fmt;
 use std::str::FromStr;
 
-#[derive(PartialEq, Debug)]
+/// The EXT header, which is required in search responses but always empty
+#[derive(PartialEq, Debug, HeaderValue)]
+#[value("")]
 pub struct Ext;
 
-impl ToString for Ext {
-    fn to_string(&self) -> String {
-        String::new()
-    }
-}
-
-impl FromStr for Ext {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "" => Ok(Self {}),
-            _ => Err(Error::IncorrectHeader("ext")),
-        }
-    }
-}
-
-#[derive(PartialEq, Debug, Default)]
+/// The MAN header of a search
+#[derive(PartialEq, Debug, Default, HeaderValue)]
+#[value("ssdp:discover", quoted)]
 pub struct ManDiscover;
 
-impl ToString for ManDiscover {
-    fn to_string(&self) -> String {
-        String::from("\"ssdp:discover\"")
-    }
-}
-
-impl FromStr for ManDiscover {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "ssdp:discover" | "\"ssdp:discover\"" => Ok(Self {}),
-            _ => Err(Error::IncorrectHeader("man")),
-        }
-    }
+/// The NTS header of a notification, saying what happened
+#[derive(PartialEq, Debug, Clone, HeaderValue)]
+pub enum NotificationSubType {
+    /// A device or service has joined the network
+    #[value("ssdp:alive")]
+    Alive,
+    /// A device or service is leaving the network
+    #[value("ssdp:byebye")]
+    ByeBye,
+    /// A device's boot id is about to change
+    #[value("ssdp:update")]
+    Update,
+    /// A state variable has changed (GENA eventing)
+    #[value("upnp:propchange")]
+    PropChange,
+    /// Anything else
+    #[value(other)]
+    Other(String),
 }
 
 /// What kind of control point to search for
-#[derive(PartialEq, Debug, Hash, Clone)]
+#[derive(PartialEq, Debug, Hash, Clone, Default)]
 pub enum SearchTarget {
     /// Search for all devices and services
+    #[default]
     All,
     /// Search for root devices only
     RootDevice,
@@ -77,33 +72,41 @@ pub enum SearchTarget {
     Other(String),
 }
 
-impl ToString for SearchTarget {
-    fn to_string(&self) -> std::string::String {
+impl fmt::Display for SearchTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use SearchTarget::*;
 
         match self {
-            All => "ssdp:all".to_string(),
-            RootDevice => "upnp:rootdevice".to_string(),
-            UUID(uuid) => format!("uuid:{}", uuid.to_string()),
+            All => write!(f, "ssdp:all"),
+            RootDevice => write!(f, "upnp:rootdevice"),
+            UUID(uuid) => write!(f, "uuid:{}", uuid),
             Device {
                 device_type,
                 version,
-            } => format!("urn:schemas-upnp-org:device:{}:{}", device_type, version),
+            } => write!(f, "urn:schemas-upnp-org:device:{}:{}", device_type, version),
             Service {
                 service_type,
                 version,
-            } => format!("urn:schemas-upnp-org:service:{}:{}", service_type, version),
+            } => write!(
+                f,
+                "urn:schemas-upnp-org:service:{}:{}",
+                service_type, version
+            ),
             VendorDevice {
                 domain_name,
                 device_type,
                 version,
-            } => format!("urn:{}:device:{}:{}", domain_name, device_type, version),
+            } => write!(f, "urn:{}:device:{}:{}", domain_name, device_type, version),
             VendorService {
                 domain_name,
                 service_type,
                 version,
-            } => format!("urn:{}:sercvice:{}:{}", domain_name, service_type, version),
-            Other(s) => s.to_string(),
+            } => write!(
+                f,
+                "urn:{}:sercvice:{}:{}",
+                domain_name, service_type, version
+            ),
+            Other(s) => f.write_str(s),
         }
     }
 }
@@ -141,12 +144,6 @@ impl FromStr for SearchTarget {
     }
 }
 
-impl Default for SearchTarget {
-    fn default() -> Self {
-        Self::All
-    }
-}
-
 #[derive(Default, PartialEq, Debug, Hash, Clone)]
 pub struct UniqueServiceName {
     pub uuid: String,
@@ -174,13 +171,12 @@ impl FromStr for UniqueServiceName {
     }
 }
 
-impl ToString for UniqueServiceName {
-    fn to_string(&self) -> String {
-        let out = format!("uuid:{}", self.uuid);
+impl fmt::Display for UniqueServiceName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "uuid:{}", self.uuid)?;
         if let Some(st) = &self.search_target {
-            format!("{}::{}", out, st.to_string())
-        } else {
-            out
+            write!(f, "::{}", st)?;
         }
+        Ok(())
     }
 }
@@ -1,36 +1,37 @@
 //! Packet is an unstructured intermediate representation of an SSDP UDP packet
 mod decoder;
 mod encoder;
+mod headers;
 
-use indexmap::IndexMap;
+use std::fmt;
 use std::str::FromStr;
 
 use crate::Error;
 
 pub use decoder::Decoder;
 pub use encoder::Encoder;
-pub use yooper_derive::{FromHeaders, FromPacket, ToHeaders, ToPacket};
+pub use headers::{HeaderName, HeaderValue, Headers};
+pub use yooper_derive::{FromHeaders, FromPacket, HeaderValue, ToHeaders, ToPacket};
 
 const REQUEST_LINE_NOTIFY: &str = "NOTIFY * HTTP/1.1";
 const REQUEST_LINE_M_SEARCH: &str = "M-SEARCH * HTTP/1.1";
 const REQUEST_LINE_OK: &str = "HTTP/1.1 200 OK";
 
 /// The Request line of the packet
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum PacketType {
     MSearch,
     Notify,
     Ok,
 }
 
-impl ToString for PacketType {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for PacketType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
             Self::MSearch => REQUEST_LINE_M_SEARCH,
             Self::Notify => REQUEST_LINE_NOTIFY,
             Self::Ok => REQUEST_LINE_OK,
-        }
-        .to_string()
+        })
     }
 }
 
@@ -46,10 +47,8 @@ impl FromStr for PacketType {
     }
 }
 
-/// records, in order, the headers for the packet
-pub type Headers = IndexMap<String, String>;
-
-/// A single SSDP packet
+/// A single SSDP packet.
+/// Header names and values are slices of the buffer the packet was decoded from.
 #[derive(PartialEq, Debug)]
 pub struct Packet {
     /// The request line of a packet
@@ -59,30 +58,40 @@ pub struct Packet {
 }
 
 impl Packet {
+    pub fn new(typ: PacketType, headers: Headers) -> Self {
+        Self { typ, headers }
+    }
+
     #[cfg(test)]
-    pub(crate) fn new_from_literal(typ: PacketType, headers: Vec<(&str, &str)>) -> Self {
-        let headers = headers
-            .into_iter()
-            .map(|(k, v)| (k.into(), v.into()))
-            .collect();
+    pub(crate) fn new_from_literal(
+        typ: PacketType,
+        headers: Vec<(&'static str, &'static str)>,
+    ) -> Self {
+        let headers = headers.into_iter().collect();
         Self { typ, headers }
     }
 }
 
-/// Deserialize a packet into something more structured
+/// Deserialize a packet into something more structured.
+/// Usually derived for an enum of messages, see [FromPacket](derive.FromPacket.html)
 pub trait FromPacket: std::marker::Sized {
     fn from_packet(msg: &Packet) -> Result<Self, crate::errors::Error>;
 }
 
-/// Serialize a structured representation into a packet
+/// Serialize a structured representation into a packet.
+/// Usually derived for an enum of messages, see [ToPacket](derive.ToPacket.html)
 pub trait ToPacket {
     fn to_packet(&self) -> Packet;
 }
 
+/// Deserialize the headers of a single kind of message.
+/// Usually derived for a struct, see [FromHeaders](derive.FromHeaders.html)
 pub trait FromHeaders: std::marker::Sized {
     fn from_headers(headers: &Headers) -> Result<Self, crate::errors::Error>;
 }
 
+/// Serialize a single kind of message into headers.
+/// Usually derived for a struct, see [ToHeaders](derive.ToHeaders.html)
 pub trait ToHeaders {
     fn to_headers(&self) -> Headers;
 }
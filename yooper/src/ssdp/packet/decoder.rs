@@ -1,8 +1,8 @@
 use crate::errors::Error;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use tokio_util::codec;
 
-use super::{Headers, Packet};
+use super::{HeaderName, HeaderValue, Headers, Packet};
 
 /// Turn a UDP packet into an unstructured Packet
 #[derive(Default)]
@@ -18,14 +18,14 @@ impl codec::Decoder for Decoder {
             Some(i) => i,
         };
 
-        let buf = src.split_to(end);
-        let bufstr = String::from_utf8_lossy(&buf[..buf.len() - 2]); // leave off last `\r\n`
-        let mut iter = bufstr.split("\r\n");
+        let mut buf = src.split_to(end).freeze();
+        buf.truncate(buf.len() - 2); // leave off last `\r\n`
+        let mut iter = lines(&buf);
         let reqline = iter
             .next()
             .ok_or_else(|| Error::ParseFailure("missing request line".into()))?;
 
-        let typ = reqline.parse()?;
+        let typ = std::str::from_utf8(&reqline)?.parse()?;
 
         let headers: Headers = iter.map(split_header).collect::<Result<_, Error>>()?;
 
@@ -42,15 +42,27 @@ fn find_end(src: &BytesMut) -> Option<usize> {
         .map(|(i, _)| i + 2) // include the trailing \r\n
 }
 
-fn split_header(line: &str) -> Result<(String, String), Error> {
-    let index = line
-        .find(':')
-        .ok_or_else(|| Error::ParseFailure(format!("unparseable header line: {}", line)))?;
-    let (key, val) = line.split_at(index);
-    let value = val[1..] // trim colon
-        .trim_start() // trim space
-        .into();
-    Ok((key.to_lowercase(), value))
+/// Split a buffer on `\r\n` without copying
+fn lines(buf: &Bytes) -> impl Iterator<Item = Bytes> + '_ {
+    buf.split(|&b| b == b'\n')
+        .map(move |line| buf.slice_ref(line.strip_suffix(b"\r").unwrap_or(line)))
+}
+
+fn split_header(line: Bytes) -> Result<(HeaderName, HeaderValue), Error> {
+    let index = line.iter().position(|&b| b == b':').ok_or_else(|| {
+        Error::ParseFailure(format!(
+            "unparseable header line: {}",
+            String::from_utf8_lossy(&line)
+        ))
+    })?;
+    let start = line[index + 1..] // trim colon
+        .iter()
+        .position(|b| !b.is_ascii_whitespace()) // trim space
+        .map_or(line.len(), |i| index + 1 + i);
+    Ok((
+        HeaderName::from_bytes(line.slice(..index)),
+        HeaderValue::from_bytes(line.slice(start..)),
+    ))
 }
 
 #[cfg(test)]
@@ -124,4 +136,20 @@ mod tests {
             )
         )
     }
+
+    #[test]
+    fn test_parse_preserves_raw_bytes() {
+        let mut buf = BytesMut::from(
+            &b"NOTIFY * HTTP/1.1\r\nHost: 239.255.255.250:1900\r\nX-Name: caf\xe9\r\n\r\n"[..],
+        );
+        let decoded = super::Decoder {}.decode(&mut buf).unwrap().unwrap();
+
+        let (name, _) = decoded.headers.iter().next().unwrap();
+        assert_eq!(name.as_bytes(), b"Host");
+        assert_eq!(decoded.headers.get("HOST").unwrap(), "239.255.255.250:1900");
+
+        let value = decoded.headers.get("x-name").unwrap();
+        assert_eq!(value.as_bytes(), b"caf\xe9");
+        assert!(value.to_str().is_err());
+    }
 }
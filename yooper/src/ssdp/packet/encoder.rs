@@ -1,4 +1,4 @@
-use bytes::BytesMut;
+use bytes::{BufMut, BytesMut};
 use tokio_util::codec;
 
 use super::Packet;
@@ -8,18 +8,39 @@ use std::fmt::Write;
 #[derive(Default)]
 pub struct Encoder {}
 
-/// Turn a an unstructured Packet into a UDP bytestream
+/// Turn a an unstructured Packet into a UDP bytestream.
+/// Headers are written byte-for-byte, with the casing they were created with.
 impl codec::Encoder<Packet> for Encoder {
     type Error = Error;
 
     #[allow(clippy::write_with_newline)]
     fn encode(&mut self, p: Packet, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        write!(dst, "{}\r\n", p.typ.to_string())?;
-        p.headers
-            .iter()
-            .map(|(k, v)| write!(dst, "{}: {}\r\n", k.to_uppercase(), v))
-            .collect::<Result<(), std::fmt::Error>>()?;
+        write!(dst, "{}\r\n", p.typ)?;
+        for (k, v) in p.headers.iter() {
+            dst.reserve(k.as_bytes().len() + v.as_bytes().len() + 4);
+            dst.put_slice(k.as_bytes());
+            dst.put_slice(b": ");
+            dst.put_slice(v.as_bytes());
+            dst.put_slice(b"\r\n");
+        }
         write!(dst, "\r\n")?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::ssdp::{packet::Decoder, tests::constants::*};
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder as _, Encoder as _};
+
+    #[test]
+    fn test_round_trip_preserves_bytes() {
+        let mut buf = BytesMut::from(NOTIFY_EXAMPLE);
+        let packet = Decoder {}.decode(&mut buf).unwrap().unwrap();
+
+        let mut out = BytesMut::new();
+        super::Encoder {}.encode(packet, &mut out).unwrap();
+        assert_eq!(&out[..], NOTIFY_EXAMPLE);
+    }
+}
@@ -0,0 +1,309 @@
+use bytes::Bytes;
+use std::fmt;
+use std::str;
+
+use crate::errors::Error;
+
+/// The name of a header.
+/// Compares case-insensitively, but keeps the casing it was created with.
+#[derive(Clone)]
+pub struct HeaderName(Bytes);
+
+impl HeaderName {
+    /// Create a header name from a static string without copying it
+    pub fn from_static(name: &'static str) -> Self {
+        Self(Bytes::from_static(name.as_bytes()))
+    }
+
+    /// Create a header name from a slice of a received buffer
+    pub fn from_bytes(name: Bytes) -> Self {
+        Self(name)
+    }
+
+    /// The raw bytes of the name, as they were received
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The name as a string, if it's valid UTF-8
+    pub fn to_str(&self) -> Result<&str, Error> {
+        Ok(str::from_utf8(&self.0)?)
+    }
+}
+
+impl PartialEq for HeaderName {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+impl Eq for HeaderName {}
+
+impl PartialEq<str> for HeaderName {
+    fn eq(&self, other: &str) -> bool {
+        self.0.eq_ignore_ascii_case(other.as_bytes())
+    }
+}
+
+impl PartialEq<&str> for HeaderName {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl fmt::Debug for HeaderName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&String::from_utf8_lossy(&self.0), f)
+    }
+}
+
+impl From<&'static str> for HeaderName {
+    fn from(name: &'static str) -> Self {
+        Self::from_static(name)
+    }
+}
+
+impl From<String> for HeaderName {
+    fn from(name: String) -> Self {
+        Self(name.into())
+    }
+}
+
+/// The value of a header. May contain bytes that aren't valid UTF-8
+#[derive(Clone, PartialEq, Eq)]
+pub struct HeaderValue(Bytes);
+
+impl HeaderValue {
+    /// Create a header value from a static string without copying it
+    pub fn from_static(value: &'static str) -> Self {
+        Self(Bytes::from_static(value.as_bytes()))
+    }
+
+    /// Create a header value from a slice of a received buffer
+    pub fn from_bytes(value: Bytes) -> Self {
+        Self(value)
+    }
+
+    /// The raw bytes of the value, as they were received
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The value as a string, if it's valid UTF-8
+    pub fn to_str(&self) -> Result<&str, Error> {
+        Ok(str::from_utf8(&self.0)?)
+    }
+}
+
+impl PartialEq<str> for HeaderValue {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for HeaderValue {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl fmt::Debug for HeaderValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&String::from_utf8_lossy(&self.0), f)
+    }
+}
+
+impl From<&'static str> for HeaderValue {
+    fn from(value: &'static str) -> Self {
+        Self::from_static(value)
+    }
+}
+
+impl From<String> for HeaderValue {
+    fn from(value: String) -> Self {
+        Self(value.into())
+    }
+}
+
+/// records, in order, the headers for the packet.
+/// A header may appear more than once; every occurrence is kept.
+#[derive(Clone, Debug, Default)]
+pub struct Headers {
+    entries: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// Two sets of headers are equal if each header has the same values in the same order,
+/// regardless of how headers with different names are interleaved
+impl PartialEq for Headers {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .entries
+                .iter()
+                .all(|(k, _)| self.get_all(k).eq(other.get_all(k)))
+    }
+}
+
+impl Eq for Headers {}
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a header, replacing its value in place if it's already present.
+    /// Any further occurrences of the header are removed.
+    pub fn insert<K, V>(&mut self, name: K, value: V)
+    where
+        K: Into<HeaderName>,
+        V: Into<HeaderValue>,
+    {
+        let name = name.into();
+        let mut value = Some(value.into());
+        self.entries.retain_mut(|(k, v)| {
+            if *k != name {
+                return true;
+            }
+            match value.take() {
+                Some(value) => {
+                    *v = value;
+                    true
+                }
+                None => false,
+            }
+        });
+        if let Some(value) = value {
+            self.entries.push((name, value))
+        }
+    }
+
+    /// Add a header, keeping any existing values for it
+    pub fn append<K, V>(&mut self, name: K, value: V)
+    where
+        K: Into<HeaderName>,
+        V: Into<HeaderValue>,
+    {
+        self.entries.push((name.into(), value.into()))
+    }
+
+    /// Look up the first value of a header by name, ignoring case
+    pub fn get<N>(&self, name: &N) -> Option<&HeaderValue>
+    where
+        N: ?Sized,
+        HeaderName: PartialEq<N>,
+    {
+        self.entries.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+    }
+
+    /// Every value of a header, in the order they appeared
+    pub fn get_all<'a, N>(&'a self, name: &'a N) -> impl Iterator<Item = &'a HeaderValue> + 'a
+    where
+        N: ?Sized,
+        HeaderName: PartialEq<N>,
+    {
+        self.entries
+            .iter()
+            .filter(move |(k, _)| k == name)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key<N>(&self, name: &N) -> bool
+    where
+        N: ?Sized,
+        HeaderName: PartialEq<N>,
+    {
+        self.get(name).is_some()
+    }
+
+    /// Iterate over the headers in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (&HeaderName, &HeaderValue)> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    /// The number of header lines, counting repeated headers once per occurrence
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K, V> std::iter::FromIterator<(K, V)> for Headers
+where
+    K: Into<HeaderName>,
+    V: Into<HeaderValue>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut headers = Self::new();
+        for (k, v) in iter {
+            headers.append(k, v);
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_case_insensitive() {
+        let mut headers = Headers::new();
+        headers.insert("Cache-Control", "max-age=1800");
+
+        assert_eq!(headers.get("CACHE-CONTROL").unwrap(), "max-age=1800");
+        assert_eq!(headers.get("cache-control").unwrap(), "max-age=1800");
+
+        let (name, _) = headers.iter().next().unwrap();
+        assert_eq!(name.as_bytes(), b"Cache-Control");
+    }
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut headers = Headers::new();
+        headers.insert("host", "a");
+        headers.insert("st", "b");
+        headers.insert("HOST", "c");
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers.get("host").unwrap(), "c");
+    }
+
+    #[test]
+    fn test_repeated_headers() {
+        let headers: Headers = vec![
+            ("OPT", "\"http://schemas.upnp.org/upnp/1/0/\"; ns=01"),
+            ("01-NLS", "b9200ebb-736d-4b93-bf03-835149d13983"),
+            ("opt", "\"http://example.com/ext/\"; ns=02"),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(headers.len(), 3);
+        assert_eq!(
+            headers.get("opt").unwrap(),
+            "\"http://schemas.upnp.org/upnp/1/0/\"; ns=01"
+        );
+        assert_eq!(
+            headers.get_all("Opt").collect::<Vec<_>>(),
+            vec![
+                "\"http://schemas.upnp.org/upnp/1/0/\"; ns=01",
+                "\"http://example.com/ext/\"; ns=02"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_collapses_repeats() {
+        let mut headers = Headers::new();
+        headers.append("opt", "a");
+        headers.append("st", "b");
+        headers.append("opt", "c");
+        headers.insert("OPT", "d");
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers.get_all("opt").collect::<Vec<_>>(), vec!["d"]);
+    }
+}
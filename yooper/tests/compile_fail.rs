@@ -0,0 +1,6 @@
+//! Message enums the derives should refuse to compile
+#[test]
+fn test_compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,100 @@
+//! Message types defined outside of yooper, as a downstream crate would
+use yooper::ssdp::packet::{
+    FromHeaders, FromPacket, Headers, Packet, PacketType, ToHeaders, ToPacket,
+};
+
+#[derive(ToHeaders, FromHeaders, Debug, PartialEq)]
+struct DialSearch {
+    host: String,
+    #[header("st")]
+    target: String,
+}
+
+#[derive(ToHeaders, FromHeaders, Debug, PartialEq)]
+struct DialResponse {
+    location: String,
+    #[header("st")]
+    target: String,
+    #[header("bootid.upnp.org")]
+    boot_id: Option<i32>,
+}
+
+#[derive(FromPacket, ToPacket, Debug, PartialEq)]
+enum Dial {
+    #[message(reqline = "MSearch")]
+    Search(DialSearch),
+    #[message(reqline = "Ok")]
+    Response(DialResponse),
+}
+
+mod reexported {
+    pub use yooper as upnp;
+}
+
+#[derive(ToHeaders, FromHeaders, Debug, PartialEq)]
+#[yooper(crate = "reexported::upnp")]
+struct Renamed {
+    host: String,
+}
+
+#[test]
+fn test_downstream_round_trip() {
+    let packet = Packet::new(
+        PacketType::Ok,
+        vec![
+            ("LOCATION", "http://192.168.7.20:8008/ssdp/device-desc.xml"),
+            ("ST", "urn:dial-multiscreen-org:service:dial:1"),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    let message = Dial::from_packet(&packet).unwrap();
+    assert_eq!(
+        message,
+        Dial::Response(DialResponse {
+            location: "http://192.168.7.20:8008/ssdp/device-desc.xml".into(),
+            target: "urn:dial-multiscreen-org:service:dial:1".into(),
+            boot_id: None,
+        })
+    );
+    assert_eq!(message.to_packet(), packet);
+}
+
+#[test]
+fn test_crate_path() {
+    let headers: Headers = vec![("HOST", "239.255.255.250:1900")].into_iter().collect();
+    let renamed = Renamed::from_headers(&headers).unwrap();
+    assert_eq!(renamed.to_headers(), headers);
+}
+
+yooper::control::client! {
+    mod wan_ip_connection = "src/description/testdata/wanipconnection.xml";
+}
+
+mod renamed_client {
+    yooper::control::client! {
+        #[yooper(crate = "crate::reexported::upnp")]
+        pub mod wan_ip_connection = "src/description/testdata/wanipconnection.xml";
+    }
+}
+
+#[test]
+fn test_downstream_client() {
+    assert_eq!(
+        "PendingDisconnect"
+            .parse::<wan_ip_connection::ConnectionStatus>()
+            .unwrap(),
+        wan_ip_connection::ConnectionStatus::PendingDisconnect
+    );
+    assert_eq!(
+        renamed_client::wan_ip_connection::PortMappingProtocol::Tcp.to_string(),
+        "TCP"
+    );
+    assert_eq!(
+        "SCTP"
+            .parse::<wan_ip_connection::PortMappingProtocol>()
+            .unwrap(),
+        wan_ip_connection::PortMappingProtocol::Other("SCTP".into())
+    );
+}
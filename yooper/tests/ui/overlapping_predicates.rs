@@ -0,0 +1,26 @@
+use yooper::ssdp::packet::{FromHeaders, FromPacket};
+
+#[derive(FromHeaders)]
+struct Notification {
+    nt: String,
+}
+
+// Every ssdp:alive notification also starts with ssdp:
+#[derive(FromPacket)]
+enum Unreachable {
+    #[message(reqline = "Notify", prefix("nts", "ssdp:"))]
+    Any(Notification),
+    #[message(reqline = "Notify", nts = "ssdp:alive")]
+    Alive(Notification),
+}
+
+// A packet with both headers would match either
+#[derive(FromPacket)]
+enum Ambiguous {
+    #[message(reqline = "Notify", present("sid"))]
+    Event(Notification),
+    #[message(reqline = "Notify", prefix("nt", "upnp:"))]
+    Root(Notification),
+}
+
+fn main() {}
@@ -1,37 +1,107 @@
+use crate::predicate::{check_overlaps, Predicate};
 use proc_macro2::Span;
 use syn::{
-    punctuated::Punctuated, spanned::Spanned, Attribute, Data, DeriveInput, Error, Field, Fields,
-    Ident, Lit, LitStr, MetaNameValue, Path, Result, Token, Type, Variant,
+    parenthesized,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    spanned::Spanned,
+    Attribute, Data, DeriveInput, Error, Expr, Field, Fields, Ident, Lit, LitStr, MetaNameValue,
+    Path, Result, Token, Type, Variant,
 };
 
+/// The path generated code uses to refer to the yooper crate.
+/// Set with `#[yooper(crate = "...")]` on the type; defaults to `::yooper`.
+pub fn crate_path(attrs: &[Attribute]) -> Result<Path> {
+    let attr = match attrs.iter().find(|a| a.path.is_ident("yooper")) {
+        Some(attr) => attr,
+        None => return Ok(syn::parse_quote!(::yooper)),
+    };
+
+    let arg: MetaNameValue = attr.parse_args()?;
+    if !arg.path.is_ident("crate") {
+        return Err(Error::new(arg.path.span(), "expected crate = \"...\""));
+    }
+    match arg.lit {
+        Lit::Str(s) => s.parse(),
+        lit => Err(Error::new(lit.span(), "crate should be a string")),
+    }
+}
+
 pub struct MessageVariant {
+    pub krate: Path,
     pub parent: Ident,
     pub name: Ident,
     pub reqline: Ident,
-    pub nts: Option<Lit>,
+    /// Conditions on the headers, all of which must hold for a packet to be this variant
+    pub predicates: Vec<Predicate>,
     pub struct_name: Path,
 }
 
-fn parse_annotation(attr: Attribute) -> Result<(Ident, Option<Lit>)> {
-    let attr_args: Punctuated<MetaNameValue, Token![,]> =
+/// A single argument to the `#[message(...)]` attribute
+enum MessageArg {
+    Reqline(Lit),
+    Predicate(Predicate),
+}
+
+impl Parse for MessageArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let key: Ident = input.parse()?;
+
+        if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            let lit: Lit = input.parse()?;
+            return if key == "reqline" {
+                Ok(Self::Reqline(lit))
+            } else if key == "nts" {
+                // #[message(nts = "...")] is shorthand for exact("nts", "...")
+                match lit {
+                    Lit::Str(v) => Ok(Self::Predicate(Predicate::Exact(
+                        LitStr::new("nts", key.span()),
+                        v,
+                    ))),
+                    lit => Err(Error::new(lit.span(), "nts should be a string")),
+                }
+            } else {
+                Err(Error::new(key.span(), "expected reqline or nts"))
+            };
+        }
+
+        let content;
+        parenthesized!(content in input);
+        let args: Punctuated<LitStr, Token![,]> = content.parse_terminated(|p| p.parse())?;
+        let name = key.to_string();
+        match name.as_str() {
+            "exact" | "prefix" | "present" => Ok(Self::Predicate(Predicate::from_args(
+                &name,
+                args.into_iter().collect(),
+                key.span(),
+            )?)),
+            _ => Err(Error::new(
+                key.span(),
+                "expected one of reqline, nts, exact, prefix or present",
+            )),
+        }
+    }
+}
+
+fn parse_annotation(attr: Attribute) -> Result<(Ident, Vec<Predicate>)> {
+    let span = attr.span();
+    let attr_args: Punctuated<MessageArg, Token![,]> =
         attr.parse_args_with(Punctuated::parse_separated_nonempty)?;
 
     let mut reqline = None;
-    let mut nts = None;
-
-    let span = attr_args.span();
+    let mut predicates = Vec::new();
 
     for arg in attr_args {
-        if arg.path.is_ident("reqline") {
-            reqline = Some(arg.lit);
-        } else if arg.path.is_ident("nts") {
-            nts = Some(arg.lit);
+        match arg {
+            MessageArg::Reqline(lit) => reqline = Some(lit),
+            MessageArg::Predicate(p) => predicates.push(p),
         }
     }
 
     let reqline =
         reqline.ok_or_else(|| Error::new(span, "Missing required attribute arg reqline"))?;
-    Ok((reqline_to_ident(reqline)?, nts))
+    Ok((reqline_to_ident(reqline)?, predicates))
 }
 
 fn reqline_to_ident(lit: Lit) -> Result<Ident> {
@@ -45,7 +115,7 @@ fn reqline_to_ident(lit: Lit) -> Result<Ident> {
 }
 
 impl MessageVariant {
-    fn from_variant(parent: &Ident, variant: Variant) -> Result<Option<Self>> {
+    fn from_variant(krate: &Path, parent: &Ident, variant: Variant) -> Result<Option<Self>> {
         let span = variant.span();
         let name = variant.ident;
         let parent = parent.clone(); // TODO: EKF
@@ -70,13 +140,14 @@ impl MessageVariant {
             _ => return Err(Error::new(span, "Expected path member for enum variant")),
         };
 
-        let (reqline, nts) = parse_annotation(attr)?;
+        let (reqline, predicates) = parse_annotation(attr)?;
 
         Ok(Some(Self {
+            krate: krate.clone(),
             parent,
             name,
             reqline,
-            nts,
+            predicates,
             struct_name,
         }))
     }
@@ -88,26 +159,31 @@ pub fn parse_variants(input: DeriveInput) -> Result<Vec<MessageVariant>> {
         _ => return Err(Error::new(input.span(), "Only Enums make sense here!")),
     };
 
+    let krate = crate_path(&input.attrs)?;
     let name = input.ident;
     let variants: Result<Vec<Option<MessageVariant>>> = enums
         .variants
         .into_iter()
-        .map(|v| MessageVariant::from_variant(&name, v))
+        .map(|v| MessageVariant::from_variant(&krate, &name, v))
         .collect();
-    Ok(variants?.into_iter().filter_map(|v| v).collect())
+    let variants: Vec<MessageVariant> = variants?.into_iter().flatten().collect();
+    check_overlaps(&variants)?;
+    Ok(variants)
 }
 
-fn path_is_option(path: &Path) -> bool {
-    path.segments.len() == 1 && path.segments.iter().next().unwrap().ident == "Option"
+fn path_is(path: &Path, name: &str) -> bool {
+    path.segments.len() == 1 && path.segments.iter().next().unwrap().ident == name
 }
 
 pub struct MessageStruct {
+    pub krate: Path,
     pub name: Ident,
     pub fields: Vec<VariantMember>,
 }
 
 pub fn parse_header_struct(input: DeriveInput) -> Result<MessageStruct> {
     let span = input.span();
+    let krate = crate_path(&input.attrs)?;
     let name = input.ident;
     let strct = match input.data {
         Data::Struct(e) => e,
@@ -131,39 +207,263 @@ pub fn parse_header_struct(input: DeriveInput) -> Result<MessageStruct> {
     .map(VariantMember::from_field)
     .collect::<Result<Vec<_>>>()?;
 
-    Ok(MessageStruct { name, fields })
+    Ok(MessageStruct {
+        krate,
+        name,
+        fields,
+    })
+}
+
+/// How many times a header may appear for a given field
+#[derive(Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    /// Exactly once
+    Required,
+    /// `Option<T>`: zero or one times
+    Optional,
+    /// `Vec<T>`: any number of times, in order
+    Multiple,
+}
+
+/// What to do when a header is missing
+pub enum HeaderDefault {
+    /// Use `Default::default()`
+    Trait,
+    /// Use the given expression
+    Expr(Box<Expr>),
 }
 
 pub struct VariantMember {
-    pub optional: bool,
+    pub kind: FieldKind,
     pub header: String,
+    /// Other names the header is accepted under, in order of preference
+    pub aliases: Vec<String>,
+    pub default: Option<HeaderDefault>,
+    /// A module with `from_header` and `to_header` functions to use instead of `FromStr`/`ToString`
+    pub with: Option<Path>,
     pub ident: Ident,
 }
 
+/// A single argument to the `#[header(...)]` attribute
+enum HeaderArg {
+    Name(LitStr),
+    Alias(LitStr),
+    Default(Option<Box<Expr>>),
+    With(Path),
+}
+
+impl Parse for HeaderArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        // #[header("cache-control")] is shorthand for #[header(name = "cache-control")]
+        if input.peek(LitStr) {
+            return Ok(Self::Name(input.parse()?));
+        }
+
+        let key: Ident = input.parse()?;
+        if key == "default" {
+            if input.peek(Token![=]) {
+                input.parse::<Token![=]>()?;
+                return Ok(Self::Default(Some(input.parse()?)));
+            }
+            return Ok(Self::Default(None));
+        }
+
+        input.parse::<Token![=]>()?;
+        if key == "name" {
+            Ok(Self::Name(input.parse()?))
+        } else if key == "alias" {
+            Ok(Self::Alias(input.parse()?))
+        } else if key == "with" {
+            // Accept both `with = path` and `with = "path"`, as serde does
+            if input.peek(LitStr) {
+                Ok(Self::With(input.parse::<LitStr>()?.parse()?))
+            } else {
+                Ok(Self::With(input.parse()?))
+            }
+        } else {
+            Err(Error::new(
+                key.span(),
+                "expected one of name, alias, default or with",
+            ))
+        }
+    }
+}
+
 impl VariantMember {
     fn from_field(field: Field) -> Result<Self> {
         let span = field.span();
         let ident = field
             .ident
             .ok_or_else(|| Error::new(span, "unnamed fields not supported"))?;
-        let attr = field.attrs.iter().find(|a| a.path.is_ident("header"));
-        let header = match attr {
-            Some(attr) => {
-                let lit: LitStr = attr.parse_args()?;
-                lit.value()
-            }
-            None => ident.to_string(),
-        };
 
-        let optional = match field.ty {
-            Type::Path(t) => path_is_option(&t.path),
-            _ => false,
+        let kind = match &field.ty {
+            Type::Path(t) if path_is(&t.path, "Option") => FieldKind::Optional,
+            Type::Path(t) if path_is(&t.path, "Vec") => FieldKind::Multiple,
+            _ => FieldKind::Required,
         };
 
-        Ok(Self {
-            optional,
-            header,
+        let mut member = Self {
+            kind,
+            header: ident.to_string(),
+            aliases: Vec::new(),
+            default: None,
+            with: None,
             ident,
-        })
+        };
+
+        let attr = match field.attrs.iter().find(|a| a.path.is_ident("header")) {
+            Some(attr) => attr,
+            None => return Ok(member),
+        };
+
+        let args: Punctuated<HeaderArg, Token![,]> =
+            attr.parse_args_with(Punctuated::parse_terminated)?;
+        for arg in args {
+            match arg {
+                HeaderArg::Name(lit) => member.header = lit.value(),
+                HeaderArg::Alias(lit) => member.aliases.push(lit.value()),
+                HeaderArg::Default(_) if kind != FieldKind::Required => {
+                    return Err(Error::new(
+                        attr.span(),
+                        "default only makes sense for fields that aren't Option or Vec",
+                    ))
+                }
+                HeaderArg::Default(None) => member.default = Some(HeaderDefault::Trait),
+                HeaderArg::Default(Some(expr)) => member.default = Some(HeaderDefault::Expr(expr)),
+                HeaderArg::With(path) => member.with = Some(path),
+            }
+        }
+
+        Ok(member)
+    }
+}
+
+/// A string a `#[derive(HeaderValue)]` variant is written as
+pub struct ValueString {
+    pub value: LitStr,
+    /// Written wrapped in double quotes, and accepted with or without them
+    pub quoted: bool,
+}
+
+pub enum ValueVariant {
+    /// A variant matching exactly one string
+    Literal { ident: Ident, value: ValueString },
+    /// The catch-all variant, `#[value(other)]`, holding any other string
+    Other { ident: Ident },
+}
+
+pub enum ValueType {
+    /// A unit struct that's only ever written one way
+    Struct(ValueString),
+    Enum(Vec<ValueVariant>),
+}
+
+pub struct HeaderValueType {
+    pub krate: Path,
+    pub name: Ident,
+    pub typ: ValueType,
+}
+
+enum ValueArg {
+    Value(LitStr),
+    Quoted,
+    Other,
+}
+
+impl Parse for ValueArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(LitStr) {
+            return Ok(Self::Value(input.parse()?));
+        }
+        let key: Ident = input.parse()?;
+        if key == "quoted" {
+            Ok(Self::Quoted)
+        } else if key == "other" {
+            Ok(Self::Other)
+        } else {
+            Err(Error::new(key.span(), "expected a string, quoted or other"))
+        }
     }
 }
+
+/// Parse `#[value("...", quoted)]` or `#[value(other)]`.
+/// Returns None for `other`
+fn parse_value_attr(span: Span, attrs: &[Attribute]) -> Result<Option<ValueString>> {
+    let attr = attrs
+        .iter()
+        .find(|a| a.path.is_ident("value"))
+        .ok_or_else(|| Error::new(span, "missing #[value(\"...\")] attribute"))?;
+    let args: Punctuated<ValueArg, Token![,]> =
+        attr.parse_args_with(Punctuated::parse_terminated)?;
+
+    let mut value = None;
+    let mut quoted = false;
+    let mut other = false;
+    for arg in args {
+        match arg {
+            ValueArg::Value(v) => value = Some(v),
+            ValueArg::Quoted => quoted = true,
+            ValueArg::Other => other = true,
+        }
+    }
+
+    match (value, other) {
+        (Some(value), false) => Ok(Some(ValueString { value, quoted })),
+        (None, true) if !quoted => Ok(None),
+        _ => Err(Error::new(
+            attr.span(),
+            "expected either #[value(\"...\")] or #[value(other)]",
+        )),
+    }
+}
+
+pub fn parse_header_value(input: DeriveInput) -> Result<HeaderValueType> {
+    let span = input.span();
+    let krate = crate_path(&input.attrs)?;
+    let name = input.ident;
+
+    let typ = match input.data {
+        Data::Struct(s) => {
+            if !s.fields.is_empty() {
+                return Err(Error::new(span, "only unit structs are supported"));
+            }
+            match parse_value_attr(span, &input.attrs)? {
+                Some(value) => ValueType::Struct(value),
+                None => return Err(Error::new(span, "#[value(other)] needs an enum variant")),
+            }
+        }
+        Data::Enum(e) => {
+            let mut variants = Vec::new();
+            let mut has_other = false;
+            for variant in e.variants {
+                let span = variant.span();
+                let ident = variant.ident;
+                match (parse_value_attr(span, &variant.attrs)?, &variant.fields) {
+                    (Some(value), Fields::Unit) => {
+                        variants.push(ValueVariant::Literal { ident, value })
+                    }
+                    (None, Fields::Unnamed(f)) if f.unnamed.len() == 1 && !has_other => {
+                        has_other = true;
+                        variants.push(ValueVariant::Other { ident })
+                    }
+                    (None, Fields::Unnamed(f)) if f.unnamed.len() == 1 => {
+                        return Err(Error::new(span, "only one variant can be #[value(other)]"))
+                    }
+                    (None, _) => {
+                        return Err(Error::new(
+                            span,
+                            "#[value(other)] variants should hold a single String",
+                        ))
+                    }
+                    (Some(_), _) => {
+                        return Err(Error::new(span, "only unit variants can have a value"))
+                    }
+                }
+            }
+            ValueType::Enum(variants)
+        }
+        Data::Union(_) => return Err(Error::new(span, "unions aren't supported")),
+    };
+
+    Ok(HeaderValueType { krate, name, typ })
+}
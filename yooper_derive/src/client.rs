@@ -0,0 +1,558 @@
+//! Typed service clients, generated from service descriptions (SCPD documents)
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::{
+    parse::{Parse, ParseStream},
+    Attribute, Error, LitStr, Path, Result, Token, Visibility,
+};
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::ast::crate_path;
+
+/// `pub mod name = "path/to/scpd.xml";`
+pub struct ClientInput {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    name: Ident,
+    path: LitStr,
+}
+
+impl Parse for ClientInput {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        input.parse::<Token![mod]>()?;
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let path = input.parse()?;
+        input.parse::<Option<Token![;]>>()?;
+        Ok(Self {
+            attrs,
+            vis,
+            name,
+            path,
+        })
+    }
+}
+
+/// Just enough of an XML element to read an SCPD
+struct Element {
+    name: String,
+    text: String,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn read(body: &[u8]) -> std::result::Result<Self, String> {
+        let mut stack = vec![Element {
+            name: String::new(),
+            text: String::new(),
+            children: vec![],
+        }];
+        for event in EventReader::new(body) {
+            match event.map_err(|e| e.to_string())? {
+                XmlEvent::StartElement { name, .. } => stack.push(Element {
+                    name: name.local_name,
+                    text: String::new(),
+                    children: vec![],
+                }),
+                XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                    if let Some(top) = stack.last_mut() {
+                        top.text.push_str(&text)
+                    }
+                }
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().ok_or("unbalanced document")?;
+                    stack
+                        .last_mut()
+                        .ok_or("unbalanced document")?
+                        .children
+                        .push(element);
+                }
+                _ => (),
+            }
+        }
+        stack
+            .pop()
+            .and_then(|document| document.children.into_iter().next())
+            .ok_or_else(|| "empty document".to_owned())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    /// The text of a required child
+    fn field(&self, name: &str) -> std::result::Result<&str, String> {
+        self.child(name)
+            .map(|c| c.text.trim())
+            .ok_or_else(|| format!("<{}> without <{}>", self.name, name))
+    }
+
+    /// Children of a list element like `<actionList>`, which may be missing
+    fn list<'a>(&'a self, list: &str, item: &'a str) -> Vec<&'a Element> {
+        self.child(list)
+            .map_or(vec![], |list| list.children(item).collect())
+    }
+}
+
+struct StateVariable<'a> {
+    name: &'a str,
+    data_type: &'a str,
+    allowed_values: Vec<&'a str>,
+}
+
+struct Argument<'a> {
+    name: &'a str,
+    input: bool,
+    variable: &'a StateVariable<'a>,
+}
+
+/// How a variable's values are represented in Rust
+struct Conversion {
+    typ: TokenStream,
+    /// From `v`, of type `typ`, to a `Value`
+    to_value: TokenStream,
+    /// From `v`, a `Value`, to an `Option` of `typ`
+    from_value: TokenStream,
+}
+
+pub fn generate(input: ClientInput) -> Result<TokenStream> {
+    let krate = module_path(crate_path(&input.attrs)?);
+    let attrs: Vec<_> = input
+        .attrs
+        .iter()
+        .filter(|a| !a.path.is_ident("yooper"))
+        .collect();
+    let ClientInput {
+        vis, name, path, ..
+    } = &input;
+
+    let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into());
+    let file = PathBuf::from(root).join(path.value());
+    let body = std::fs::read(&file).map_err(|e| {
+        Error::new(
+            path.span(),
+            format!("couldn't read {}: {}", file.display(), e),
+        )
+    })?;
+    let scpd = Element::read(&body).map_err(|e| Error::new(path.span(), e))?;
+    let file = file.to_string_lossy().into_owned();
+
+    let fail = |e: String| Error::new(path.span(), e);
+    let variables = scpd
+        .list("serviceStateTable", "stateVariable")
+        .into_iter()
+        .map(|v| {
+            Ok(StateVariable {
+                name: v.field("name")?,
+                data_type: v.field("dataType")?,
+                allowed_values: v
+                    .list("allowedValueList", "allowedValue")
+                    .into_iter()
+                    .map(|a| a.text.trim())
+                    .collect(),
+            })
+        })
+        .collect::<std::result::Result<Vec<_>, String>>()
+        .map_err(fail)?;
+
+    let mut enums = Vec::new();
+    for variable in variables.iter().filter(|v| is_enum(v)) {
+        enums.push(allowed_values_enum(&krate, variable).map_err(fail)?);
+    }
+
+    let mut methods = Vec::new();
+    let mut responses = Vec::new();
+    for action in scpd.list("actionList", "action") {
+        let action_name = action.field("name").map_err(fail)?;
+        let arguments = action
+            .list("argumentList", "argument")
+            .into_iter()
+            .map(|a| {
+                let related = a.field("relatedStateVariable")?;
+                Ok(Argument {
+                    name: a.field("name")?,
+                    input: a.field("direction")? == "in",
+                    variable: variables
+                        .iter()
+                        .find(|v| v.name == related)
+                        .ok_or_else(|| format!("no state variable {}", related))?,
+                })
+            })
+            .collect::<std::result::Result<Vec<_>, String>>()
+            .map_err(fail)?;
+        let (method, response) = action_method(&krate, action_name, &arguments);
+        methods.push(method);
+        responses.extend(response);
+    }
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis mod #name {
+            /// The service description this client was generated from
+            pub const SCPD: &str = include_str!(#file);
+
+            /// Invokes the service's actions, checking arguments against its description first
+            pub struct Client<P = #krate::description::HttpFetcher> {
+                service: #krate::description::Service,
+                scpd: #krate::description::ServiceDescription,
+                client: P,
+            }
+
+            impl Client {
+                /// `service` should come from `describe`, so its control URL is absolute
+                pub fn new(
+                    service: #krate::description::Service,
+                ) -> ::std::result::Result<Self, #krate::Error> {
+                    Self::with_client(service, #krate::description::HttpFetcher::default())
+                }
+            }
+
+            impl<P: #krate::description::Post> Client<P> {
+                pub fn with_client(
+                    service: #krate::description::Service,
+                    client: P,
+                ) -> ::std::result::Result<Self, #krate::Error> {
+                    ::std::result::Result::Ok(Self {
+                        service,
+                        scpd: SCPD.parse()?,
+                        client,
+                    })
+                }
+
+                pub fn service(&self) -> &#krate::description::Service {
+                    &self.service
+                }
+
+                pub fn client(&self) -> &P {
+                    &self.client
+                }
+
+                #(#methods)*
+            }
+
+            #(#enums)*
+
+            #(#responses)*
+        }
+    })
+}
+
+/// Generated code lives in a new module, so relative paths need to start from its parent
+fn module_path(krate: Path) -> TokenStream {
+    let first = krate.segments.first().map(|s| s.ident.to_string());
+    if krate.leading_colon.is_some() || first.as_deref() == Some("crate") {
+        quote! { #krate }
+    } else {
+        quote! { super::#krate }
+    }
+}
+
+/// Strings with an `allowedValueList` become enums
+fn is_enum(variable: &StateVariable) -> bool {
+    variable.data_type == "string" && !variable.allowed_values.is_empty()
+}
+
+fn allowed_values_enum(
+    krate: &TokenStream,
+    variable: &StateVariable,
+) -> std::result::Result<TokenStream, String> {
+    let name = format_ident!("{}", camel_case(variable.name));
+    let krate_string = krate.to_string();
+
+    // Devices send values the SCPD doesn't list, so there's always somewhere to put them
+    let mut seen: HashSet<_> = vec!["Other".to_owned()].into_iter().collect();
+    let mut variants = Vec::new();
+    for value in &variable.allowed_values {
+        let variant = format_ident!("{}", camel_case(value));
+        if !seen.insert(variant.to_string()) {
+            return Err(format!(
+                "{} has allowed values that are both named {}",
+                variable.name, variant
+            ));
+        }
+        variants.push(quote! {
+            #[value(#value)]
+            #variant,
+        });
+    }
+
+    let doc = format!(
+        "The allowed values of `{}`, and any others a device uses",
+        variable.name
+    );
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Debug, PartialEq, Eq, Clone, Hash, #krate::ssdp::packet::HeaderValue)]
+        #[yooper(crate = #krate_string)]
+        pub enum #name {
+            #(#variants)*
+            #[value(other)]
+            Other(String),
+        }
+    })
+}
+
+fn conversion(krate: &TokenStream, variable: &StateVariable) -> Conversion {
+    let value = quote! { #krate::description::Value };
+    if is_enum(variable) {
+        let typ = format_ident!("{}", camel_case(variable.name));
+        return Conversion {
+            typ: quote! { #typ },
+            to_value: quote! { #value::String(v.to_string()) },
+            from_value: quote! {
+                match v {
+                    #value::String(s) => s.parse().ok(),
+                    _ => None,
+                }
+            },
+        };
+    }
+
+    let simple = |variant: &str, typ: TokenStream| {
+        let variant = format_ident!("{}", variant);
+        Conversion {
+            typ,
+            to_value: quote! { #value::#variant(v) },
+            from_value: quote! {
+                match v {
+                    #value::#variant(v) => Some(v),
+                    _ => None,
+                }
+            },
+        }
+    };
+    let date = quote! { #krate::description::Date };
+    let time = quote! { #krate::description::Time };
+    let zone = quote! { #krate::description::TimeZone };
+
+    match variable.data_type {
+        "ui1" => simple("UI1", quote! { u8 }),
+        "ui2" => simple("UI2", quote! { u16 }),
+        "ui4" => simple("UI4", quote! { u32 }),
+        "ui8" => simple("UI8", quote! { u64 }),
+        "i1" => simple("I1", quote! { i8 }),
+        "i2" => simple("I2", quote! { i16 }),
+        "i4" => simple("I4", quote! { i32 }),
+        "i8" => simple("I8", quote! { i64 }),
+        "int" => simple("Int", quote! { i64 }),
+        "r4" => simple("R4", quote! { f32 }),
+        "r8" => simple("R8", quote! { f64 }),
+        "number" => simple("Number", quote! { f64 }),
+        "fixed.14.4" => simple("Fixed14_4", quote! { i64 }),
+        "float" => simple("Float", quote! { f64 }),
+        "char" => simple("Char", quote! { char }),
+        "string" => simple("String", quote! { String }),
+        "date" => simple("Date", date),
+        "time" => simple("Time", time),
+        "boolean" => simple("Boolean", quote! { bool }),
+        "bin.base64" => simple("BinBase64", quote! { Vec<u8> }),
+        "bin.hex" => simple("BinHex", quote! { Vec<u8> }),
+        "uri" => simple("URI", quote! { String }),
+        "uuid" => simple("UUID", quote! { #krate::description::Uuid }),
+        "dateTime" => Conversion {
+            typ: quote! { (#date, Option<#time>) },
+            to_value: quote! { #value::DateTime(v.0, v.1) },
+            from_value: quote! {
+                match v {
+                    #value::DateTime(d, t) => Some((d, t)),
+                    _ => None,
+                }
+            },
+        },
+        "dateTime.tz" => Conversion {
+            typ: quote! { (#date, Option<#time>, Option<#zone>) },
+            to_value: quote! { #value::DateTimeTZ(v.0, v.1, v.2) },
+            from_value: quote! {
+                match v {
+                    #value::DateTimeTZ(d, t, z) => Some((d, t, z)),
+                    _ => None,
+                }
+            },
+        },
+        "time.tz" => Conversion {
+            typ: quote! { (#time, Option<#zone>) },
+            to_value: quote! { #value::TimeTZ(v.0, v.1) },
+            from_value: quote! {
+                match v {
+                    #value::TimeTZ(t, z) => Some((t, z)),
+                    _ => None,
+                }
+            },
+        },
+        other => Conversion {
+            typ: quote! { String },
+            to_value: quote! { #value::Other(#other.to_owned(), v) },
+            from_value: quote! {
+                match v {
+                    #value::Other(_, v) => Some(v),
+                    _ => None,
+                }
+            },
+        },
+    }
+}
+
+/// A method invoking the action, and the struct it returns if there's more than one out-argument
+fn action_method(
+    krate: &TokenStream,
+    action: &str,
+    arguments: &[Argument],
+) -> (TokenStream, Option<TokenStream>) {
+    let method = snake_ident(action);
+
+    let inputs: Vec<_> = arguments.iter().filter(|a| a.input).collect();
+    let params = inputs.iter().map(|a| {
+        let ident = snake_ident(a.name);
+        let typ = conversion(krate, a.variable).typ;
+        quote! { #ident: #typ }
+    });
+    let values = inputs.iter().map(|a| {
+        let name = a.name;
+        let ident = snake_ident(a.name);
+        let to_value = conversion(krate, a.variable).to_value;
+        quote! { (#name, { let v = #ident; #to_value }) }
+    });
+
+    let outputs: Vec<_> = arguments.iter().filter(|a| !a.input).collect();
+    let take = |a: &Argument| {
+        let name = a.name;
+        let from_value = conversion(krate, a.variable).from_value;
+        quote! { #krate::control::take_out_argument(&mut out, #name, |v| #from_value) }
+    };
+    let (output, result, response) = match outputs.as_slice() {
+        [] => (
+            quote! { () },
+            quote! { ::std::result::Result::Ok(()) },
+            None,
+        ),
+        [only] => (conversion(krate, only.variable).typ, take(only), None),
+        outputs => {
+            let response = format_ident!("{}Response", camel_case(action));
+            let fields: Vec<_> = outputs.iter().map(|a| snake_ident(a.name)).collect();
+            let types = outputs.iter().map(|a| conversion(krate, a.variable).typ);
+            let docs = outputs.iter().map(|a| format!("`{}`", a.name));
+            let takes = outputs.iter().map(|a| take(a));
+            let doc = format!("The out-arguments of `{}`", action);
+            (
+                quote! { #response },
+                quote! { ::std::result::Result::Ok(#response { #(#fields: #takes?,)* }) },
+                Some(quote! {
+                    #[doc = #doc]
+                    #[derive(Debug, PartialEq, Clone)]
+                    pub struct #response {
+                        #(
+                            #[doc = #docs]
+                            pub #fields: #types,
+                        )*
+                    }
+                }),
+            )
+        }
+    };
+
+    let doc = format!("Invoke `{}`", action);
+    let method = quote! {
+        #[doc = #doc]
+        #[allow(clippy::too_many_arguments)]
+        pub async fn #method(
+            &self,
+            #(#params),*
+        ) -> ::std::result::Result<#output, #krate::Error> {
+            let arguments = vec![#(#values),*];
+            #[allow(unused_mut, unused_variables)]
+            let mut out = #krate::control::invoke_checked_with(
+                &self.client,
+                &self.service,
+                &self.scpd,
+                #action,
+                &arguments,
+            )
+            .await?;
+            #result
+        }
+    };
+    (method, response)
+}
+
+/// `NewExternalIPAddress` to `new_external_ip_address`
+fn snake_case(name: &str) -> String {
+    words(name)
+        .iter()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// `ERROR_NONE` to `ErrorNone`, `TCP` to `Tcp`
+fn camel_case(name: &str) -> String {
+    let camel: String = words(name)
+        .iter()
+        .map(|w| {
+            let mut chars = w.chars();
+            chars.next().map_or(String::new(), |first| {
+                first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect()
+            })
+        })
+        .collect();
+    match camel.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => camel,
+        _ => format!("V{}", camel),
+    }
+}
+
+/// Split an identifier into words at punctuation and case changes,
+/// keeping acronyms like `IP` together
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let previous = if i > 0 { chars.get(i - 1) } else { None };
+        let next = chars.get(i + 1);
+        let boundary = c.is_ascii_uppercase()
+            && previous.is_some_and(|p| {
+                p.is_ascii_lowercase()
+                    || p.is_ascii_digit()
+                    || (p.is_ascii_uppercase() && next.is_some_and(|n| n.is_ascii_lowercase()))
+            });
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn snake_ident(name: &str) -> Ident {
+    let snake = snake_case(name);
+    let snake = match snake.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => snake,
+        _ => format!("_{}", snake),
+    };
+    match syn::parse_str::<Ident>(&snake) {
+        Ok(ident) => ident,
+        Err(_) => format_ident!("r#{}", snake),
+    }
+}
@@ -1,13 +1,16 @@
 use crate::ast::{
-    parse_header_struct, parse_variants, MessageStruct, MessageVariant, VariantMember,
+    crate_path, parse_header_struct, parse_variants, FieldKind, HeaderDefault, MessageStruct,
+    MessageVariant, VariantMember,
 };
 use proc_macro2::TokenStream;
 use quote::{quote, ToTokens};
-use syn::{DeriveInput, Result};
+use syn::{DeriveInput, Path, Result};
 
 // #[derive(ToMessage, FromMessage)]
 // enum Packet {
 //   #[message(reqline = Notify, nts = "ssdp:alive")]
+//   or, equivalently
+//   #[message(reqline = Notify, exact("nts", "ssdp:alive"))]
 //   Alive (Alive)
 //     #[header("ssdpuuid.upnp.org")
 //     uuid: String
@@ -24,8 +27,8 @@ use syn::{DeriveInput, Result};
 struct FromPacket<'a>(&'a MessageVariant);
 
 impl MessageVariant {
-    fn from_message(&self) -> FromPacket {
-        FromPacket(&self)
+    fn as_from_packet(&self) -> FromPacket<'_> {
+        FromPacket(self)
     }
 }
 
@@ -33,13 +36,17 @@ impl<'a> FromPacket<'a> {
     fn as_from_condition(&self) -> TokenStream {
         let mut tokens = TokenStream::new();
 
-        let MessageVariant { reqline, nts, .. } = &self.0;
+        let MessageVariant {
+            krate,
+            reqline,
+            predicates,
+            ..
+        } = &self.0;
 
-        tokens.extend(quote! { packet.typ == crate::ssdp::packet::PacketType::#reqline});
-        if let Some(nts) = nts {
-            tokens.extend(quote! {
-                && packet.headers.get("nts").map_or(false, |h| h == #nts )
-            })
+        tokens.extend(quote! { packet.typ == #krate::ssdp::packet::PacketType::#reqline});
+        for predicate in predicates {
+            let cond = predicate.condition();
+            tokens.extend(quote! { && #cond })
         }
 
         tokens
@@ -49,6 +56,7 @@ impl<'a> FromPacket<'a> {
 impl<'a> ToTokens for FromPacket<'a> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let MessageVariant {
+            krate,
             name,
             parent,
             struct_name,
@@ -57,8 +65,8 @@ impl<'a> ToTokens for FromPacket<'a> {
         let cond = self.as_from_condition();
         tokens.extend(quote! {
             if #cond {
-                return Ok(
-                    #parent::#name(#struct_name::from_headers(&packet.headers)?)
+                return ::std::result::Result::Ok(
+                    #parent::#name(<#struct_name as #krate::ssdp::packet::FromHeaders>::from_headers(&packet.headers)?)
                 )
             }
         });
@@ -66,30 +74,97 @@ impl<'a> ToTokens for FromPacket<'a> {
 }
 
 impl VariantMember {
-    fn from_message(&self) -> FromPacketField {
-        FromPacketField(&self)
+    fn as_from_packet<'a>(&'a self, krate: &'a Path) -> FromPacketField<'a> {
+        FromPacketField(self, krate)
     }
 }
 
-struct FromPacketField<'a>(&'a VariantMember);
+struct FromPacketField<'a>(&'a VariantMember, &'a Path);
+
+impl<'a> FromPacketField<'a> {
+    /// An expression for the first value of the header, trying aliases in order
+    fn lookup(&self) -> TokenStream {
+        let VariantMember {
+            header, aliases, ..
+        } = self.0;
+        quote! {
+            headers.get(#header)#(.or_else(|| headers.get(#aliases)))*
+        }
+    }
+
+    /// An iterator expression over every value of the header, under any of its names
+    fn lookup_all(&self) -> TokenStream {
+        let VariantMember {
+            header, aliases, ..
+        } = self.0;
+        if aliases.is_empty() {
+            quote! { headers.get_all(#header) }
+        } else {
+            quote! {
+                headers
+                    .iter()
+                    .filter(|(k, _)| *k == #header #(|| *k == #aliases)*)
+                    .map(|(_, v)| v)
+            }
+        }
+    }
+
+    /// An expression turning the HeaderValue `v` into the field's type
+    fn parse(&self) -> TokenStream {
+        match &self.0.with {
+            Some(with) => quote! { #with::from_header(v.to_str()?)? },
+            None => quote! { v.to_str()?.parse()? },
+        }
+    }
+}
 
 impl<'a> ToTokens for FromPacketField<'a> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let VariantMember {
-            optional,
+            kind,
             header,
+            default,
             ident,
             ..
         } = self.0;
 
-        let q = if *optional {
-            quote! {
-                #ident: headers.get(#header).map_or(Ok(None), |v| v.parse().map(Some))?
+        let krate = self.1;
+        let lookup = self.lookup();
+        let parse = self.parse();
+
+        let q = match (kind, default) {
+            (FieldKind::Optional, _) => quote! {
+                #ident: match #lookup {
+                    Some(v) => Some(#parse),
+                    None => None,
+                }
+            },
+            (FieldKind::Multiple, _) => {
+                let lookup_all = self.lookup_all();
+                quote! {
+                    #ident: #lookup_all
+                        .map(|v| -> ::std::result::Result<_, #krate::Error> { ::std::result::Result::Ok(#parse) })
+                        .collect::<::std::result::Result<_, _>>()?
+                }
             }
-        } else {
-            quote! {
-                #ident: headers.get(#header).ok_or_else(|| crate::Error::MissingHeader(#header))?.parse()?
+            (FieldKind::Required, Some(default)) => {
+                let default = match default {
+                    HeaderDefault::Trait => quote! { ::std::default::Default::default() },
+                    HeaderDefault::Expr(expr) => quote! { #expr },
+                };
+                quote! {
+                    #ident: match #lookup {
+                        Some(v) => #parse,
+                        None => #default,
+                    }
+                }
             }
+            (FieldKind::Required, None) => quote! {
+                #ident: {
+                    let v = #lookup.ok_or_else(|| #krate::Error::MissingHeader(#header))?;
+                    #parse
+                }
+            },
         };
 
         tokens.extend(q);
@@ -99,18 +174,22 @@ impl<'a> ToTokens for FromPacketField<'a> {
 struct FromHeaders<'a>(&'a MessageStruct);
 
 impl MessageStruct {
-    fn from_headers(&self) -> FromHeaders {
-        FromHeaders(&self)
+    fn as_from_headers(&self) -> FromHeaders<'_> {
+        FromHeaders(self)
     }
 }
 
 impl<'a> ToTokens for FromHeaders<'a> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        let fields = self.0.fields.iter().map(VariantMember::from_message);
-        let name = &self.0.name;
+        let MessageStruct {
+            krate,
+            name,
+            fields,
+        } = self.0;
+        let fields = fields.iter().map(|f| f.as_from_packet(krate));
 
         tokens.extend(quote! {
-           Ok(#name {
+           ::std::result::Result::Ok(#name {
                 #(#fields),*
            })
         })
@@ -120,15 +199,16 @@ impl<'a> ToTokens for FromHeaders<'a> {
 pub fn headers(input: DeriveInput) -> Result<TokenStream> {
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
     let msgstruct = parse_header_struct(input.clone())?;
-    let headers = msgstruct.from_headers();
+    let headers = msgstruct.as_from_headers();
+    let krate = &msgstruct.krate;
 
     let name = input.ident;
 
     let tokens = quote! {
         #[automatically_derived]
-        impl #impl_generics crate::ssdp::packet::FromHeaders for #name #ty_generics #where_clause {
+        impl #impl_generics #krate::ssdp::packet::FromHeaders for #name #ty_generics #where_clause {
 
-            fn from_headers(headers: &crate::ssdp::packet::Headers) -> Result<Self, crate::Error> {
+            fn from_headers(headers: &#krate::ssdp::packet::Headers) -> ::std::result::Result<Self, #krate::Error> {
                 #headers
             }
         }
@@ -137,19 +217,23 @@ pub fn headers(input: DeriveInput) -> Result<TokenStream> {
 }
 
 pub fn derive(input: DeriveInput) -> Result<TokenStream> {
+    let krate = crate_path(&input.attrs)?;
     let variants = parse_variants(input.clone())?; // TODO(EKF)
-    let variants: Vec<FromPacket> = variants.iter().map(MessageVariant::from_message).collect();
+    let variants: Vec<FromPacket> = variants
+        .iter()
+        .map(MessageVariant::as_from_packet)
+        .collect();
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     let name = input.ident;
 
     let tokens = quote! {
         #[automatically_derived]
-        impl #impl_generics crate::ssdp::packet::FromPacket for #name #ty_generics #where_clause {
-            fn from_packet(packet: &crate::ssdp::packet::Packet) -> Result<Self, crate::Error> {
+        impl #impl_generics #krate::ssdp::packet::FromPacket for #name #ty_generics #where_clause {
+            fn from_packet(packet: &#krate::ssdp::packet::Packet) -> ::std::result::Result<Self, #krate::Error> {
                 #(#variants)*;
 
-                Err(crate::Error::UnknownPacket)
+                ::std::result::Result::Err(#krate::Error::UnknownPacket)
             }
         }
     };
@@ -0,0 +1,108 @@
+use crate::ast::{parse_header_value, HeaderValueType, ValueString, ValueType, ValueVariant};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, Result};
+
+impl ValueString {
+    /// The patterns this value is accepted as
+    fn pattern(&self) -> TokenStream {
+        let value = &self.value;
+        if self.quoted {
+            let quoted = format!("\"{}\"", value.value());
+            quote! { #value | #quoted }
+        } else {
+            quote! { #value }
+        }
+    }
+
+    /// The string this value is written as
+    fn output(&self) -> TokenStream {
+        let value = &self.value;
+        if self.quoted {
+            let quoted = format!("\"{}\"", value.value());
+            quote! { #quoted }
+        } else {
+            quote! { #value }
+        }
+    }
+}
+
+pub fn derive(input: DeriveInput) -> Result<TokenStream> {
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let HeaderValueType { krate, name, typ } = parse_header_value(input.clone())?;
+    let type_name = name.to_string();
+    let unexpected = quote! {
+        ::std::result::Result::Err(#krate::Error::UnexpectedValue(#type_name, s.to_owned()))
+    };
+
+    let (from_str, display) = match typ {
+        ValueType::Struct(value) => {
+            let pattern = value.pattern();
+            let output = value.output();
+            (
+                quote! {
+                    match s {
+                        #pattern => ::std::result::Result::Ok(Self),
+                        _ => #unexpected,
+                    }
+                },
+                quote! { f.write_str(#output) },
+            )
+        }
+        ValueType::Enum(variants) => {
+            let mut other = None;
+            let mut from_arms = Vec::new();
+            let mut display_arms = Vec::new();
+            for variant in variants {
+                match variant {
+                    ValueVariant::Literal { ident, value } => {
+                        let pattern = value.pattern();
+                        let output = value.output();
+                        from_arms
+                            .push(quote! { #pattern => ::std::result::Result::Ok(Self::#ident), });
+                        display_arms.push(quote! { Self::#ident => f.write_str(#output), });
+                    }
+                    ValueVariant::Other { ident } => {
+                        display_arms.push(quote! { Self::#ident(s) => f.write_str(s), });
+                        other = Some(ident);
+                    }
+                }
+            }
+            let fallback = match other {
+                Some(ident) => quote! { ::std::result::Result::Ok(Self::#ident(s.to_owned())) },
+                None => unexpected,
+            };
+            (
+                quote! {
+                    match s {
+                        #(#from_arms)*
+                        _ => #fallback,
+                    }
+                },
+                quote! {
+                    match self {
+                        #(#display_arms)*
+                    }
+                },
+            )
+        }
+    };
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics ::std::str::FromStr for #name #ty_generics #where_clause {
+            type Err = #krate::Error;
+
+            fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+                #from_str
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics ::std::fmt::Display for #name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                #display
+            }
+        }
+    })
+}
@@ -1,12 +1,27 @@
+//! Derive macros for turning SSDP packets into structured messages and back.
+//! These are re-exported from `yooper::ssdp::packet`.
+//! There's also `client!`, re-exported from `yooper::control`, for typed service clients.
+//!
+//! Generated code refers to the yooper crate as `::yooper`.
+//! If you've renamed it, or are re-exporting it, set the path with `#[yooper(crate = "...")]`.
 mod ast;
+mod client;
 mod from_packet;
+mod header_value;
+mod predicate;
 mod to_packet;
 
 extern crate proc_macro;
 
 use syn::{parse_macro_input, DeriveInput};
 
-#[proc_macro_derive(FromPacket, attributes(message))]
+/// Implement `FromPacket` for an enum of messages.
+/// Each variant wraps a single type implementing `FromHeaders`, and is annotated with
+/// `#[message(reqline = "...")]` naming the `PacketType` it's sent with.
+/// Variants sharing a request line are told apart by predicates on their headers:
+/// `exact("nts", "ssdp:alive")` (or the shorthand `nts = "ssdp:alive"`), `prefix("nt", "upnp:")`
+/// and `present("sid")`. Variants that could match the same packet are a compile error.
+#[proc_macro_derive(FromPacket, attributes(message, yooper))]
 pub fn derive_from_packet(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let e = parse_macro_input!(input as DeriveInput);
     from_packet::derive(e)
@@ -14,7 +29,8 @@ pub fn derive_from_packet(input: proc_macro::TokenStream) -> proc_macro::TokenSt
         .into()
 }
 
-#[proc_macro_derive(ToPacket, attributes(message))]
+/// Implement `ToPacket` for an enum of messages, annotated as for `FromPacket`.
+#[proc_macro_derive(ToPacket, attributes(message, yooper))]
 pub fn derive_to_packet(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let e = parse_macro_input!(input as DeriveInput);
     to_packet::derive(e)
@@ -22,7 +38,8 @@ pub fn derive_to_packet(input: proc_macro::TokenStream) -> proc_macro::TokenStre
         .into()
 }
 
-#[proc_macro_derive(ToHeaders, attributes(header))]
+/// Implement `ToHeaders` for a struct, one header per field, annotated as for `FromHeaders`.
+#[proc_macro_derive(ToHeaders, attributes(header, yooper))]
 pub fn derive_to_headers(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let e = parse_macro_input!(input as DeriveInput);
     to_packet::headers(e)
@@ -30,10 +47,40 @@ pub fn derive_to_headers(input: proc_macro::TokenStream) -> proc_macro::TokenStr
         .into()
 }
 
-#[proc_macro_derive(FromHeaders, attributes(header))]
+/// Implement `FromHeaders` for a struct, one header per field.
+/// Fields are named after their header unless annotated with
+/// `#[header(name = "...", alias = "...", default = ..., with = path)]`.
+/// `Option<T>` fields may be missing, and `Vec<T>` fields collect repeated headers.
+#[proc_macro_derive(FromHeaders, attributes(header, yooper))]
 pub fn derive_from_headers(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let e = parse_macro_input!(input as DeriveInput);
     from_packet::headers(e)
         .unwrap_or_else(|err| err.to_compile_error())
         .into()
 }
+
+/// Implement `FromStr` and `Display` for a header value that's one of a fixed set of strings.
+/// Works on unit structs and on enums of unit variants, each annotated with `#[value("...")]`.
+/// Add `quoted` to write the value in double quotes (and accept it with or without them).
+/// An enum may have one `#[value(other)]` variant holding a `String` to catch anything else.
+#[proc_macro_derive(HeaderValue, attributes(value, yooper))]
+pub fn derive_header_value(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let e = parse_macro_input!(input as DeriveInput);
+    header_value::derive(e)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+/// Generate a typed client for a service from its description (SCPD) document.
+/// `pub mod name = "path/to/scpd.xml";` reads the path relative to your crate's root,
+/// and produces a module holding a `Client` with one async method per action,
+/// an enum for each string state variable with an `allowedValueList`
+/// (with an `Other(String)` variant for values the list leaves out),
+/// and a struct for each action returning more than one out-argument.
+#[proc_macro]
+pub fn client(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as client::ClientInput);
+    client::generate(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
@@ -0,0 +1,179 @@
+use crate::ast::MessageVariant;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Error, LitStr, Result};
+
+/// A condition on a header that a packet must meet to be parsed as a particular variant
+pub enum Predicate {
+    /// `exact("nts", "ssdp:alive")`: the header has exactly this value
+    Exact(LitStr, LitStr),
+    /// `prefix("nt", "upnp:")`: the header's value starts with this
+    Prefix(LitStr, LitStr),
+    /// `present("sid")`: the header is set, to anything
+    Present(LitStr),
+}
+
+impl Predicate {
+    pub fn from_args(kind: &str, args: Vec<LitStr>, span: proc_macro2::Span) -> Result<Self> {
+        let mut args = args.into_iter();
+        let pred = match (kind, args.next(), args.next()) {
+            ("exact", Some(h), Some(v)) => Self::Exact(h, v),
+            ("prefix", Some(h), Some(v)) => Self::Prefix(h, v),
+            ("present", Some(h), None) => Self::Present(h),
+            ("present", _, _) => return Err(Error::new(span, "expected present(\"header\")")),
+            _ => {
+                return Err(Error::new(
+                    span,
+                    format!("expected {}(\"header\", \"value\")", kind),
+                ))
+            }
+        };
+        if args.next().is_some() {
+            return Err(Error::new(span, "too many arguments"));
+        }
+        Ok(pred)
+    }
+
+    fn header(&self) -> String {
+        match self {
+            Self::Exact(h, _) | Self::Prefix(h, _) | Self::Present(h) => h.value().to_lowercase(),
+        }
+    }
+
+    /// An expression that's true if `packet` satisfies this predicate
+    pub fn condition(&self) -> TokenStream {
+        match self {
+            Self::Exact(h, v) => quote! {
+                packet.headers.get(#h).map_or(false, |h| h == #v)
+            },
+            Self::Prefix(h, v) => quote! {
+                packet.headers.get(#h).map_or(false, |h| h.as_bytes().starts_with(#v.as_bytes()))
+            },
+            Self::Present(h) => quote! {
+                packet.headers.contains_key(#h)
+            },
+        }
+    }
+
+    /// Every packet satisfying `self` also satisfies `other`
+    fn implies(&self, other: &Self) -> bool {
+        if self.header() != other.header() {
+            return false;
+        }
+        match (self, other) {
+            (_, Self::Present(_)) => true,
+            (Self::Exact(_, a), Self::Exact(_, b)) => a.value() == b.value(),
+            (Self::Exact(_, a), Self::Prefix(_, b)) | (Self::Prefix(_, a), Self::Prefix(_, b)) => {
+                a.value().starts_with(&b.value())
+            }
+            _ => false,
+        }
+    }
+
+    /// Some packet could satisfy both `self` and `other`
+    fn compatible(&self, other: &Self) -> bool {
+        if self.header() != other.header() {
+            return true;
+        }
+        match (self, other) {
+            (Self::Present(_), _) | (_, Self::Present(_)) => true,
+            (Self::Exact(_, a), Self::Exact(_, b)) => a.value() == b.value(),
+            (Self::Exact(_, a), Self::Prefix(_, b)) | (Self::Prefix(_, b), Self::Exact(_, a)) => {
+                a.value().starts_with(&b.value())
+            }
+            (Self::Prefix(_, a), Self::Prefix(_, b)) => {
+                a.value().starts_with(&b.value()) || b.value().starts_with(&a.value())
+            }
+        }
+    }
+}
+
+/// Variants are tried in order, so make sure no packet could match more than one of them
+pub fn check_overlaps(variants: &[MessageVariant]) -> Result<()> {
+    let mut errors: Option<Error> = None;
+
+    for (i, later) in variants.iter().enumerate() {
+        for earlier in &variants[..i] {
+            if earlier.reqline != later.reqline {
+                continue;
+            }
+
+            let subsumed = earlier
+                .predicates
+                .iter()
+                .all(|p| later.predicates.iter().any(|q| q.implies(p)));
+            let overlaps = earlier
+                .predicates
+                .iter()
+                .all(|p| later.predicates.iter().all(|q| p.compatible(q)));
+
+            let message = if subsumed {
+                format!(
+                    "{} is unreachable: every packet it matches is already matched by {}",
+                    later.name, earlier.name
+                )
+            } else if overlaps {
+                format!(
+                    "{} and {} can match the same packet; add a predicate to tell them apart",
+                    earlier.name, later.name
+                )
+            } else {
+                continue;
+            };
+
+            let error = Error::new(later.name.span(), message);
+            match &mut errors {
+                Some(errors) => errors.combine(error),
+                None => errors = Some(error),
+            }
+        }
+    }
+
+    match errors {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Predicate::{self, *};
+    use proc_macro2::Span;
+    use syn::LitStr;
+
+    fn lit(s: &str) -> LitStr {
+        LitStr::new(s, Span::call_site())
+    }
+
+    fn exact(h: &str, v: &str) -> Predicate {
+        Exact(lit(h), lit(v))
+    }
+
+    fn prefix(h: &str, v: &str) -> Predicate {
+        Prefix(lit(h), lit(v))
+    }
+
+    #[test]
+    fn test_implies() {
+        assert!(exact("NTS", "ssdp:alive").implies(&exact("nts", "ssdp:alive")));
+        assert!(exact("nt", "upnp:event").implies(&prefix("nt", "upnp:")));
+        assert!(prefix("nt", "upnp:ev").implies(&prefix("nt", "upnp:")));
+        assert!(prefix("nt", "upnp:").implies(&Present(lit("nt"))));
+
+        assert!(!prefix("nt", "upnp:").implies(&exact("nt", "upnp:event")));
+        assert!(!exact("nts", "ssdp:alive").implies(&exact("nts", "ssdp:byebye")));
+        assert!(!exact("nts", "ssdp:alive").implies(&Present(lit("nt"))));
+    }
+
+    #[test]
+    fn test_compatible() {
+        assert!(!exact("nts", "ssdp:alive").compatible(&exact("nts", "ssdp:byebye")));
+        assert!(!exact("nts", "ssdp:alive").compatible(&prefix("nts", "upnp:")));
+        assert!(!prefix("nts", "ssdp:").compatible(&prefix("nts", "upnp:")));
+
+        assert!(exact("nts", "ssdp:alive").compatible(&prefix("nts", "ssdp:")));
+        assert!(prefix("nts", "ssdp:").compatible(&prefix("nts", "ssdp:al")));
+        assert!(exact("nts", "ssdp:alive").compatible(&exact("nt", "upnp:event")));
+        assert!(Present(lit("sid")).compatible(&exact("sid", "uuid:1")));
+    }
+}
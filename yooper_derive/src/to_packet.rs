@@ -1,6 +1,8 @@
 use crate::ast::{
-    parse_header_struct, parse_variants, MessageStruct, MessageVariant, VariantMember,
+    crate_path, parse_header_struct, parse_variants, FieldKind, MessageStruct, MessageVariant,
+    VariantMember,
 };
+use crate::predicate::Predicate;
 use proc_macro2::TokenStream;
 use quote::{quote, ToTokens};
 use syn::{DeriveInput, Result};
@@ -8,35 +10,38 @@ use syn::{DeriveInput, Result};
 struct ToPacket<'a>(&'a MessageVariant);
 
 impl MessageVariant {
-    fn to_message(&self) -> ToPacket {
-        ToPacket(&self)
+    fn to_message(&self) -> ToPacket<'_> {
+        ToPacket(self)
     }
 }
 
 impl<'a> ToTokens for ToPacket<'a> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let MessageVariant {
+            krate,
             name,
             parent,
             reqline,
-            nts,
+            predicates,
             ..
         } = &self.0;
 
-        let nts_header = if let Some(nts) = nts {
-            quote! {
-                headers.insert("nts".to_string(), #nts.to_string());
+        // Headers with an exact value are implied by the variant, so set them here.
+        // Other predicates have to be satisfied by the struct's own fields.
+        let exact_headers = predicates.iter().filter_map(|p| match p {
+            Predicate::Exact(h, v) => {
+                let h = h.value().to_uppercase();
+                Some(quote! { headers.insert(#h, #v); })
             }
-        } else {
-            quote! {}
-        };
+            _ => None,
+        });
 
         tokens.extend(quote! {
             #parent::#name ( field ) => {
-                let mut headers = field.to_headers();
-                #nts_header
-                crate::ssdp::packet::Packet {
-                    typ: crate::ssdp::packet::PacketType::#reqline,
+                let mut headers = #krate::ssdp::packet::ToHeaders::to_headers(field);
+                #(#exact_headers)*
+                #krate::ssdp::packet::Packet {
+                    typ: #krate::ssdp::packet::PacketType::#reqline,
                     headers,
                 }
             }
@@ -45,8 +50,8 @@ impl<'a> ToTokens for ToPacket<'a> {
 }
 
 impl VariantMember {
-    fn to_message(&self) -> ToPacketField {
-        ToPacketField(&self)
+    fn to_message(&self) -> ToPacketField<'_> {
+        ToPacketField(self)
     }
 }
 
@@ -55,19 +60,40 @@ struct ToPacketField<'a>(&'a VariantMember);
 impl<'a> ToTokens for ToPacketField<'a> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let VariantMember {
-            optional,
+            kind,
             header,
+            with,
             ident,
+            ..
         } = &self.0;
-        let t = if *optional {
-            quote! {
-                if let Some(v) = &self.#ident {
-                    headers.insert(#header.to_string(), v.to_string());
+        // SSDP headers are conventionally written in upper case
+        let header = header.to_uppercase();
+        let format = |v: TokenStream| match with {
+            Some(with) => quote! { #with::to_header(#v) },
+            None => quote! { ::std::string::ToString::to_string(#v) },
+        };
+        let t = match kind {
+            FieldKind::Optional => {
+                let v = format(quote! { v });
+                quote! {
+                    if let Some(v) = &self.#ident {
+                        headers.insert(#header, #v);
+                    }
                 }
             }
-        } else {
-            quote! {
-                headers.insert(#header.to_string(), self.#ident.to_string());
+            FieldKind::Multiple => {
+                let v = format(quote! { v });
+                quote! {
+                    for v in &self.#ident {
+                        headers.append(#header, #v);
+                    }
+                }
+            }
+            FieldKind::Required => {
+                let v = format(quote! { &self.#ident });
+                quote! {
+                    headers.insert(#header, #v);
+                }
             }
         };
         tokens.extend(t)
@@ -77,17 +103,18 @@ impl<'a> ToTokens for ToPacketField<'a> {
 struct ToHeaders<'a>(&'a MessageStruct);
 
 impl MessageStruct {
-    fn to_headers(&self) -> ToHeaders {
-        ToHeaders(&self)
+    fn to_headers(&self) -> ToHeaders<'_> {
+        ToHeaders(self)
     }
 }
 
 impl<'a> ToTokens for ToHeaders<'a> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        let fields = self.0.fields.iter().map(VariantMember::to_message);
+        let MessageStruct { krate, fields, .. } = self.0;
+        let fields = fields.iter().map(VariantMember::to_message);
 
         tokens.extend(quote! {
-            let mut headers = crate::ssdp::packet::Headers::new();
+            let mut headers = #krate::ssdp::packet::Headers::new();
             #(#fields)*
             headers
         })
@@ -98,12 +125,13 @@ pub fn headers(input: DeriveInput) -> Result<TokenStream> {
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
     let msgstruct = parse_header_struct(input.clone())?;
     let headers = msgstruct.to_headers();
+    let krate = &msgstruct.krate;
 
     let name = input.ident;
     let tokens = quote! {
         #[automatically_derived]
-        impl #impl_generics crate::ssdp::packet::ToHeaders for #name #ty_generics #where_clause {
-            fn to_headers(&self) -> crate::ssdp::packet::Headers {
+        impl #impl_generics #krate::ssdp::packet::ToHeaders for #name #ty_generics #where_clause {
+            fn to_headers(&self) -> #krate::ssdp::packet::Headers {
                 #headers
             }
         }
@@ -113,6 +141,7 @@ pub fn headers(input: DeriveInput) -> Result<TokenStream> {
 }
 
 pub fn derive(input: DeriveInput) -> Result<TokenStream> {
+    let krate = crate_path(&input.attrs)?;
     let variants = parse_variants(input.clone())?; // TODO(EKF)
     let variants: Vec<ToPacket> = variants.iter().map(MessageVariant::to_message).collect();
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
@@ -121,8 +150,8 @@ pub fn derive(input: DeriveInput) -> Result<TokenStream> {
 
     let tokens = quote! {
         #[automatically_derived]
-        impl #impl_generics crate::ssdp::packet::ToPacket for #name #ty_generics #where_clause {
-            fn to_packet(&self) -> crate::ssdp::packet::Packet {
+        impl #impl_generics #krate::ssdp::packet::ToPacket for #name #ty_generics #where_clause {
+            fn to_packet(&self) -> #krate::ssdp::packet::Packet {
                 match self {
                     #(#variants)*,
 